@@ -1,11 +1,12 @@
 use bevy_pixel_buffer::prelude::*;
-use std::cmp::Ordering;
 
 use bevy::prelude::Component;
 use bevy::prelude::Vec2;
-use bevy::utils::FloatOrd;
 
-#[derive(Component, Debug, Clone, Copy)]
+use crate::geometry::{distance_to_segment, signed_area, winding, Winding};
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct PixColor(pub u8, pub u8, pub u8, pub u8);
 
 impl From<Pixel> for PixColor {
@@ -25,78 +26,425 @@ impl From<PixColor> for Pixel {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Names an image in the texture folder of the assets, like `"Bricks_01-128x128.png"`,
+/// see [`TextureRegistry`](crate::texture::TextureRegistry)
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(transparent))]
+pub struct TextureId(pub String);
+
+/// World units one repeat of an unscaled texture covers, on walls as well as floors and roofs
+pub const TEXTURE_SIZE: f32 = 16.0;
+
+/// Stable identifier of a sector inside a [`Level`].
+///
+/// Unlike an index into [`Level::sectors`] it stays the same when other sectors are removed,
+/// so walls can refer to their neighbours with it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(transparent))]
+pub struct SectorId(pub u32);
+
+/// Which edge of a wall its texture starts from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum Peg {
+    /// The bottom of the texture sits on the floor, or on the lower edge of a step wall
+    #[default]
+    Bottom,
+    /// The top of the texture hangs from the roof, or from the upper edge of a step wall
+    Top,
+}
+
+/// Where a texture sits on a wall. Everything is in world units so textures keep the
+/// same size on walls of any length or height.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextureMapping {
+    /// Moves the texture along the wall and upwards
+    pub offset: Vec2,
+    /// Stretches the texture, 2 makes one repeat twice as big as [`TEXTURE_SIZE`]
+    pub scale: Vec2,
+    pub peg: Peg,
+}
+
+impl Default for TextureMapping {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            scale: Vec2::ONE,
+            peg: Peg::default(),
+        }
+    }
+}
+
+impl TextureMapping {
+    /// Texture coordinates in repeats of the texture, for the point `along` world units from the
+    /// start of a wall at `height`, on a wall reaching from `bottom` to `top`
+    pub fn uv(&self, along: f32, height: f32, (bottom, top): (f32, f32)) -> Vec2 {
+        let anchor = match self.peg {
+            Peg::Bottom => bottom,
+            Peg::Top => top,
+        };
+        (Vec2::new(along, height - anchor) + self.offset) / (self.scale * TEXTURE_SIZE)
+    }
+
+    /// The mapping for a wall following on from one `length` long, so the texture carries on
+    /// across the corner without a seam
+    pub fn continued(&self, length: f32) -> Self {
+        Self {
+            offset: Vec2::new(self.offset.x + length, self.offset.y),
+            ..*self
+        }
+    }
+}
+
+/// Look of one of the step walls above or below a portal
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct WallTexture {
+    pub color: PixColor,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub mapping: TextureMapping,
+    /// Drawn instead of the flat color when set
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub texture: Option<TextureId>,
+}
+
+impl Default for WallTexture {
+    fn default() -> Self {
+        Self {
+            color: PixColor(255, 255, 255, 255),
+            mapping: TextureMapping::default(),
+            texture: None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Wall {
     pub points: [Vec2; 2],
     pub color: PixColor, // height: f32,
-    pub uv: Vec2,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub mapping: TextureMapping,
+    /// Drawn instead of the flat color when set
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub texture: Option<TextureId>,
+    /// Added to the light of the sector, to brighten or darken this wall and its step walls
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub light: f32,
+    /// Sector this wall opens into, `None` for a solid wall
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub portal: Option<SectorId>,
+    /// Drawn from the neighbours roof up to ours when it is lower
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub upper: WallTexture,
+    /// Drawn from our floor up to the neighbours when it is higher
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub lower: WallTexture,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum Surface {
-    Top,
-    Bottom,
-    Normal,
+impl Wall {
+    pub fn is_portal(&self) -> bool {
+        self.portal.is_some()
+    }
+
+    pub fn length(&self) -> f32 {
+        self.points[0].distance(self.points[1])
+    }
+
+    pub fn distance_to(&self, point: Vec2) -> f32 {
+        distance_to_segment(point, self.points[0], self.points[1])
+    }
+
+    /// Whether `other` runs along the same edge in the opposite direction,
+    /// which is how the two sides of a portal line up
+    pub fn is_reverse_of(&self, other: &Wall) -> bool {
+        self.points[0] == other.points[1] && self.points[1] == other.points[0]
+    }
 }
 
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sector {
+    /// Assigned by [`Level::add_sector`]
+    pub id: SectorId,
     pub walls: Vec<Wall>,
-    pub center: Vec2,
-    pub depth: f32,
     pub roof: f32, // Top and bottom height of walls
     pub floor: f32,
     pub roof_col: PixColor,
     pub floor_col: PixColor,
-    pub surface: Surface,
-    pub x_points: Vec<u32>,
+    /// Drawn instead of the flat colors when set
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub roof_texture: Option<TextureId>,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub floor_texture: Option<TextureId>,
+    /// How brightly lit everything in the sector is, from 0 for black to 1 for full brightness
+    #[cfg_attr(feature = "serialize", serde(default = "full_light"))]
+    pub light: f32,
 }
 
-impl Ord for Sector {
-    fn cmp(&self, other: &Self) -> Ordering {
-        FloatOrd(self.depth).cmp(&FloatOrd(other.depth))
-    }
-}
-
-impl PartialOrd for Sector {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl PartialEq for Sector {
-    fn eq(&self, other: &Self) -> bool {
-        self.depth == other.depth
-    }
+#[cfg(feature = "serialize")]
+fn full_light() -> f32 {
+    1.0
 }
 
-impl Eq for Sector {}
-
 impl Sector {
     pub fn new(floor: f32, roof: f32) -> Self {
         Self {
+            id: SectorId::default(),
             roof,
             floor,
-            depth: 0.0,
-            center: Vec2::ZERO,
             walls: Vec::default(),
             roof_col: PixColor(0, 0, 255, 255),
             floor_col: PixColor(0, 255, 0, 255),
-            surface: Surface::Normal,
-            x_points: vec![0; 240 * 360],
+            roof_texture: None,
+            floor_texture: None,
+            light: 1.0,
         }
     }
 
     pub fn add_wall(&mut self, bottom_one: Vec2, bottom_two: Vec2, color: PixColor) {
+        let texture = WallTexture {
+            color,
+            ..Default::default()
+        };
         self.walls.push(Wall {
             points: [bottom_one, bottom_two],
             color,
-            uv: Vec2::new(5.0, 1.0), // Should be calculated in editor
-                                     // uv: Vec2::ONE,
+            mapping: TextureMapping::default(),
+            texture: None,
+            light: 0.0,
+            portal: None,
+            upper: texture.clone(),
+            lower: texture,
         });
     }
+
+    /// Adds a wall that opens into the `neighbour` sector instead of blocking the view
+    pub fn add_portal(
+        &mut self,
+        bottom_one: Vec2,
+        bottom_two: Vec2,
+        color: PixColor,
+        neighbour: SectorId,
+    ) {
+        self.add_wall(bottom_one, bottom_two, color);
+        if let Some(wall) = self.walls.last_mut() {
+            wall.portal = Some(neighbour);
+        }
+    }
+
+    /// Whether a point on the map lies inside the walls of this sector
+    pub fn contains(&self, point: Vec2) -> bool {
+        // Even-odd rule so neither wall order nor winding matter
+        let mut inside = false;
+        for wall in self.walls.iter() {
+            let [a, b] = wall.points;
+            if (a.y > point.y) != (b.y > point.y)
+                && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Twice the area inside the walls, negative while they run clockwise
+    pub fn signed_area(&self) -> f32 {
+        signed_area(self.walls.iter().map(|wall| wall.points))
+    }
+
+    pub fn winding(&self) -> Option<Winding> {
+        winding(self.walls.iter().map(|wall| wall.points))
+    }
+
+    /// Turns the walls around when they run counter-clockwise, so every wall
+    /// faces into the sector no matter which way it was drawn
+    pub fn normalize_winding(&mut self) {
+        if self.winding() == Some(Winding::CounterClockwise) {
+            self.walls.reverse();
+            for wall in self.walls.iter_mut() {
+                wall.points.swap(0, 1);
+            }
+        }
+    }
+
+    /// Lines the textures of the walls following `index` up with it, each wall carrying on where
+    /// the one before it ends. Stops at the first wall with a different texture.
+    pub fn align_textures(&mut self, index: usize) {
+        let count = self.walls.len();
+        let Some(texture) = self.walls.get(index).map(|wall| wall.texture.clone()) else {
+            return;
+        };
+        for step in 1..count {
+            let previous = &self.walls[(index + step - 1) % count];
+            let length = previous.length();
+            let mappings = [
+                previous.mapping,
+                previous.upper.mapping,
+                previous.lower.mapping,
+            ];
+            let wall = &mut self.walls[(index + step) % count];
+            if wall.texture != texture {
+                break;
+            }
+            wall.mapping = mappings[0].continued(length);
+            wall.upper.mapping = mappings[1].continued(length);
+            wall.lower.mapping = mappings[2].continued(length);
+        }
+    }
+
+    /// Sectors this one opens into through its portal walls
+    pub fn neighbours(&self) -> impl Iterator<Item = SectorId> + '_ {
+        self.walls.iter().filter_map(|wall| wall.portal)
+    }
 }
 
-#[derive(Component, Default)]
+#[derive(Component, Clone, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Level {
+    /// Use [`Level::add_sector`] rather than pushing here so every sector gets a unique id
     pub sectors: Vec<Sector>,
+    // Worked out again from the sector ids when loading
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub(crate) next_id: u32,
+}
+
+impl Level {
+    /// Adds the sector to the level and returns the id it was given
+    pub fn add_sector(&mut self, mut sector: Sector) -> SectorId {
+        let id = SectorId(self.next_id);
+        self.next_id += 1;
+        sector.id = id;
+        sector.normalize_winding();
+        self.sectors.push(sector);
+        id
+    }
+
+    /// The id the next call to [`Level::add_sector`] will hand out,
+    /// useful for building portals into a sector that isn't added yet
+    pub fn next_id(&self) -> SectorId {
+        SectorId(self.next_id)
+    }
+
+    pub fn index_of(&self, id: SectorId) -> Option<usize> {
+        self.sectors.iter().position(|sector| sector.id == id)
+    }
+
+    pub fn sector(&self, id: SectorId) -> Option<&Sector> {
+        self.sectors.iter().find(|sector| sector.id == id)
+    }
+
+    pub fn sector_mut(&mut self, id: SectorId) -> Option<&mut Sector> {
+        self.sectors.iter_mut().find(|sector| sector.id == id)
+    }
+
+    /// Removes a sector and turns every portal that led into it back into a solid wall
+    pub fn remove_sector(&mut self, id: SectorId) -> Option<Sector> {
+        let removed = self.sectors.remove(self.index_of(id)?);
+        for wall in self
+            .sectors
+            .iter_mut()
+            .flat_map(|sector| sector.walls.iter_mut())
+        {
+            if wall.portal == Some(id) {
+                wall.portal = None;
+            }
+        }
+        Some(removed)
+    }
+
+    /// Removes a wall from a sector and closes the outline again by stretching the wall before it
+    /// to where the removed one ended. A sector left with fewer than three walls is removed whole.
+    /// Portals through the removed or the stretched wall become solid on both sides.
+    pub fn remove_wall(&mut self, id: SectorId, index: usize) -> Option<Wall> {
+        let sector = self.sector_mut(id)?;
+        if index >= sector.walls.len() {
+            return None;
+        }
+        let removed = sector.walls.remove(index);
+        self.unlink(id, &removed);
+
+        let sector = self.sector_mut(id)?;
+        let count = sector.walls.len();
+        if count < 3 {
+            self.remove_sector(id);
+            return Some(removed);
+        }
+        let previous = &mut sector.walls[(index + count - 1) % count];
+        let before = previous.clone();
+        previous.points[1] = removed.points[1];
+        previous.portal = None;
+        self.unlink(id, &before);
+        Some(removed)
+    }
+
+    /// Makes the wall on the other side of a portal out of `id` solid again
+    fn unlink(&mut self, id: SectorId, wall: &Wall) {
+        if let Some(neighbour) = wall.portal.and_then(|portal| self.sector_mut(portal)) {
+            for other in neighbour.walls.iter_mut() {
+                if other.portal == Some(id) && other.is_reverse_of(wall) {
+                    other.portal = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::polygon;
+
+    fn outline(sector: &Sector) -> Vec<[Vec2; 2]> {
+        sector.walls.iter().map(|wall| wall.points).collect()
+    }
+
+    #[test]
+    fn turns_counter_clockwise_walls_around() {
+        let mut sector = polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        sector.walls[1].color = PixColor(255, 0, 0, 255);
+        assert_eq!(sector.winding(), Some(Winding::CounterClockwise));
+
+        sector.normalize_winding();
+        assert_eq!(sector.winding(), Some(Winding::Clockwise));
+        assert!(sector.signed_area() < 0.0);
+        // Still a closed outline, with each wall keeping its look on the same edge
+        for (index, wall) in sector.walls.iter().enumerate() {
+            let next = &sector.walls[(index + 1) % sector.walls.len()];
+            assert_eq!(wall.points[1], next.points[0]);
+        }
+        let red = sector
+            .walls
+            .iter()
+            .find(|wall| wall.color == PixColor(255, 0, 0, 255))
+            .unwrap();
+        assert_eq!(red.points, [Vec2::new(10.0, 10.0), Vec2::new(10.0, 0.0)]);
+    }
+
+    #[test]
+    fn leaves_clockwise_walls_alone() {
+        let mut sector = polygon(&[(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0)]);
+        let before = outline(&sector);
+        sector.normalize_winding();
+        assert_eq!(outline(&sector), before);
+    }
+
+    #[test]
+    fn added_sectors_face_inwards() {
+        let mut level = Level::default();
+        let id = level.add_sector(polygon(&[
+            (0.0, 0.0),
+            (20.0, 0.0),
+            (20.0, 10.0),
+            (10.0, 5.0),
+            (0.0, 10.0),
+        ]));
+        assert_eq!(
+            level.sector(id).and_then(Sector::winding),
+            Some(Winding::Clockwise)
+        );
+    }
 }
@@ -0,0 +1,59 @@
+//! Helpers for working with points and walls on the map
+
+use bevy::prelude::Vec2;
+
+/// Point on the segment from `start` to `end` that is closest to `point`
+pub fn closest_point_on_segment(point: Vec2, start: Vec2, end: Vec2) -> Vec2 {
+    let direction = end - start;
+    let length_squared = direction.length_squared();
+    if length_squared == 0.0 {
+        return start;
+    }
+    let factor = ((point - start).dot(direction) / length_squared).clamp(0.0, 1.0);
+    start + direction * factor
+}
+
+pub fn distance_to_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    point.distance(closest_point_on_segment(point, start, end))
+}
+
+/// Twice the area enclosed by the edges, negative when they run clockwise
+pub fn signed_area(edges: impl IntoIterator<Item = [Vec2; 2]>) -> f32 {
+    edges.into_iter().map(|[a, b]| a.perp_dot(b)).sum()
+}
+
+/// Where two segments cross, touching at an end or running parallel doesn't count
+pub fn segment_intersection(first: [Vec2; 2], second: [Vec2; 2]) -> Option<Vec2> {
+    let [a, b] = first;
+    let [c, d] = second;
+    let direction = b - a;
+    let other = d - c;
+    let denominator = direction.perp_dot(other);
+    if denominator.abs() <= f32::EPSILON {
+        return None;
+    }
+    let t = (c - a).perp_dot(other) / denominator;
+    let u = (c - a).perp_dot(direction) / denominator;
+    let inside = |factor: f32| factor > 1e-4 && factor < 1.0 - 1e-4;
+    (inside(t) && inside(u)).then(|| a + direction * t)
+}
+
+/// Which way round a closed outline runs when seen from above
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Winding {
+    /// The way sector walls run, so they face into the sector
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Winding of a closed outline, `None` when it encloses no area
+pub fn winding(edges: impl IntoIterator<Item = [Vec2; 2]>) -> Option<Winding> {
+    let area = signed_area(edges);
+    if area < 0.0 {
+        Some(Winding::Clockwise)
+    } else if area > 0.0 {
+        Some(Winding::CounterClockwise)
+    } else {
+        None
+    }
+}
@@ -1,4 +1,18 @@
 pub mod define;
+pub mod geometry;
+pub mod lookup;
+pub mod portals;
+#[cfg(feature = "serialize")]
+pub mod serialize;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod texture;
+pub mod validate;
 pub mod prelude {
     pub use crate::define::*;
+    pub use crate::lookup::SectorGrid;
+    #[cfg(feature = "serialize")]
+    pub use crate::serialize::LevelError;
+    pub use crate::texture::{TexturePlugin, TextureRegistry};
+    pub use crate::validate::{Diagnostic, DiagnosticKind};
 }
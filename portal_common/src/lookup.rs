@@ -0,0 +1,165 @@
+//! Finding the sector a point on the map lies in
+
+use bevy::prelude::{Component, Vec2};
+
+use crate::define::{Level, Sector, SectorId};
+
+/// Most columns or rows a [`SectorGrid`] gets, a far flung wall makes the cells bigger instead
+const MAX_CELLS_ACROSS: usize = 256;
+
+impl Sector {
+    /// Lowest and highest corner of the box around the walls, `None` without walls
+    pub fn bounds(&self) -> Option<(Vec2, Vec2)> {
+        let mut points = self.walls.iter().flat_map(|wall| wall.points);
+        let first = points.next()?;
+        Some(points.fold((first, first), |(min, max), point| {
+            (min.min(point), max.max(point))
+        }))
+    }
+}
+
+impl Level {
+    /// The sector `point` lies in, checking every sector in turn.
+    /// Use a [`SectorGrid`] when looking up many points in a big level.
+    pub fn sector_at(&self, point: Vec2) -> Option<SectorId> {
+        self.sectors
+            .iter()
+            .find(|sector| sector.contains(point))
+            .map(|sector| sector.id)
+    }
+}
+
+/// Buckets sectors into square cells of the map so a lookup only checks the few sectors
+/// that reach into the cell of the point. Has to be built again when the level changes.
+#[derive(Component, Clone, Debug)]
+pub struct SectorGrid {
+    origin: Vec2,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    /// Sectors whose bounds overlap each cell, row by row
+    cells: Vec<Vec<SectorId>>,
+}
+
+impl SectorGrid {
+    pub fn new(level: &Level, cell_size: f32) -> Self {
+        let bounds: Vec<_> = level
+            .sectors
+            .iter()
+            .filter_map(|sector| Some((sector.id, sector.bounds()?)))
+            .collect();
+        let (min, max) = bounds
+            .iter()
+            .map(|(_, bounds)| *bounds)
+            .reduce(|(min, max), (low, high)| (min.min(low), max.max(high)))
+            .unwrap_or_default();
+
+        // Grow the cells until the level fits, which also covers a cell size of zero or less
+        let extent = max - min;
+        let fitting = extent.max_element() / MAX_CELLS_ACROSS as f32;
+        let cell_size = match cell_size.max(fitting) {
+            size if size > 0.0 && size.is_finite() => size,
+            _ => 1.0,
+        };
+        // Casting saturates, the limit still holds when the extent is out of any range
+        let cells = |length: f32| ((length / cell_size) as usize).min(MAX_CELLS_ACROSS - 1) + 1;
+        let (columns, rows) = (cells(extent.x), cells(extent.y));
+        let mut grid = Self {
+            origin: min,
+            cell_size,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+        };
+        for (id, (low, high)) in bounds {
+            let (low, high) = (grid.cell_of(low), grid.cell_of(high));
+            for row in low.1..=high.1 {
+                for column in low.0..=high.0 {
+                    grid.cells[row * grid.columns + column].push(id);
+                }
+            }
+        }
+        grid
+    }
+
+    /// Same as [`Level::sector_at`] for the level the grid was built from
+    pub fn sector_at(&self, level: &Level, point: Vec2) -> Option<SectorId> {
+        let (column, row) = self.cell_of(point);
+        self.cells[row * self.columns + column]
+            .iter()
+            .copied()
+            .find(|id| {
+                level
+                    .sector(*id)
+                    .is_some_and(|sector| sector.contains(point))
+            })
+    }
+
+    /// Cell the point lies in, points off the grid go to the nearest cell on its edge.
+    /// No sector reaches out there, so they are only checked against sectors they aren't in.
+    fn cell_of(&self, point: Vec2) -> (usize, usize) {
+        // Negative cells saturate to 0
+        let cell = ((point - self.origin) / self.cell_size).floor();
+        (
+            (cell.x as usize).min(self.columns - 1),
+            (cell.y as usize).min(self.rows - 1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{polygon, rectangle};
+
+    fn level() -> Level {
+        let mut level = Level::default();
+        level.add_sector(rectangle((0.0, 0.0), (10.0, 10.0)));
+        level.add_sector(rectangle((10.0, 0.0), (20.0, 10.0)));
+        level.add_sector(rectangle((0.0, 10.0), (20.0, 25.0)));
+        level.add_sector(polygon(&[(20.0, 0.0), (30.0, 25.0), (30.0, 0.0)]));
+        level
+    }
+
+    /// Points every 2.5 units from outside the bounds on one side to the other,
+    /// so they also land on the corners and the edges the sectors share
+    fn lattice() -> impl Iterator<Item = Vec2> {
+        (-4..=16).flat_map(|x| (-4..=14).map(move |y| Vec2::new(x as f32, y as f32) * 2.5))
+    }
+
+    fn assert_agrees(level: &Level, grid: &SectorGrid) {
+        for point in lattice() {
+            assert_eq!(
+                grid.sector_at(level, point),
+                level.sector_at(point),
+                "at {point}"
+            );
+        }
+    }
+
+    #[test]
+    fn grid_agrees_with_checking_every_sector() {
+        let level = level();
+        for cell_size in [1.0, 2.5, 4.0, 7.5, 100.0] {
+            assert_agrees(&level, &SectorGrid::new(&level, cell_size));
+        }
+    }
+
+    #[test]
+    fn grid_stays_small_around_a_stray_point() {
+        let mut level = level();
+        let stray = level.add_sector(rectangle((1e6, 1e6), (1e6 + 1.0, 1e6 + 1.0)));
+        let grid = SectorGrid::new(&level, 1.0);
+        assert!(grid.columns <= MAX_CELLS_ACROSS && grid.rows <= MAX_CELLS_ACROSS);
+        assert_agrees(&level, &grid);
+        assert_eq!(grid.sector_at(&level, Vec2::splat(1e6 + 0.5)), Some(stray));
+    }
+
+    #[test]
+    fn grid_works_with_cells_too_small_to_use() {
+        let level = level();
+        for cell_size in [0.0, -1.0, f32::NAN] {
+            assert_agrees(&level, &SectorGrid::new(&level, cell_size));
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Turning walls that sectors share into portals
+
+use bevy::prelude::Vec2;
+
+use crate::define::{Level, SectorId, Wall};
+
+/// How far off a line a point may be and still count as lying on it, relative to the walls length
+const COLLINEAR_TOLERANCE: f32 = 1e-4;
+
+impl Level {
+    /// Links every wall that runs back along a wall of another sector as a portal between the two.
+    ///
+    /// Walls that only share part of an edge are split first so the shared part becomes a wall
+    /// of its own on both sides. Returns how many walls were linked.
+    pub fn link_portals(&mut self) -> usize {
+        self.split_shared_walls();
+
+        let mut links: Vec<(SectorId, usize, SectorId)> = Vec::new();
+        for sector in self.sectors.iter() {
+            for (index, wall) in sector.walls.iter().enumerate() {
+                let neighbour = self.sectors.iter().find(|other| {
+                    other.id != sector.id
+                        && other.walls.iter().any(|other| other.is_reverse_of(wall))
+                });
+                if let Some(neighbour) = neighbour {
+                    if wall.portal != Some(neighbour.id) {
+                        links.push((sector.id, index, neighbour.id));
+                    }
+                }
+            }
+        }
+
+        for (id, index, neighbour) in links.iter() {
+            if let Some(wall) = self
+                .sector_mut(*id)
+                .and_then(|sector| sector.walls.get_mut(*index))
+            {
+                wall.portal = Some(*neighbour);
+            }
+        }
+        links.len()
+    }
+
+    /// Splits walls where a wall of another sector running the opposite way starts or ends along them
+    fn split_shared_walls(&mut self) {
+        let mut splits: Vec<(usize, usize, Vec<Vec2>)> = Vec::new();
+        for (sector_index, sector) in self.sectors.iter().enumerate() {
+            for (wall_index, wall) in sector.walls.iter().enumerate() {
+                let mut points: Vec<Vec2> = self
+                    .sectors
+                    .iter()
+                    .filter(|other| other.id != sector.id)
+                    .flat_map(|other| other.walls.iter())
+                    .filter(|other| runs_back_along(wall, other))
+                    .flat_map(|other| other.points)
+                    .filter(|point| lies_inside(wall, *point))
+                    .collect();
+                if points.is_empty() {
+                    continue;
+                }
+                // Order the cuts from the start of the wall and drop repeats
+                let [start, end] = wall.points;
+                let direction = end - start;
+                points.sort_by(|a, b| {
+                    (*a - start)
+                        .dot(direction)
+                        .total_cmp(&(*b - start).dot(direction))
+                });
+                points.dedup();
+                splits.push((sector_index, wall_index, points));
+            }
+        }
+
+        // Back to front so indices still to be split don't shift
+        for (sector_index, wall_index, points) in splits.into_iter().rev() {
+            let walls = &mut self.sectors[sector_index].walls;
+            let pieces = split_wall(&walls[wall_index], &points);
+            walls.splice(wall_index..=wall_index, pieces);
+        }
+    }
+}
+
+/// Whether `other` lies on the same line as `wall`, runs the other way and shares some of its length
+fn runs_back_along(wall: &Wall, other: &Wall) -> bool {
+    let [start, end] = wall.points;
+    let direction = end - start;
+    let length = direction.length();
+    if length == 0.0 || direction.dot(other.points[1] - other.points[0]) >= 0.0 {
+        return false;
+    }
+    let on_line = |point: Vec2| {
+        (point - start).perp_dot(direction).abs() / length <= COLLINEAR_TOLERANCE * length
+    };
+    if !other.points.into_iter().all(on_line) {
+        return false;
+    }
+    // Where the other wall starts and ends along ours, from 0 to 1
+    let along = other
+        .points
+        .map(|point| (point - start).dot(direction) / (length * length));
+    along[0].max(along[1]).min(1.0) > along[0].min(along[1]).max(0.0)
+}
+
+/// Whether `point` lies on `wall` somewhere between its ends
+fn lies_inside(wall: &Wall, point: Vec2) -> bool {
+    let [start, end] = wall.points;
+    point != start
+        && point != end
+        && wall.distance_to(point) <= COLLINEAR_TOLERANCE * start.distance(end)
+}
+
+/// Cuts a wall at the given points, which have to be ordered from its start. Each piece keeps the
+/// look of the wall with its textures shifted to stay where they were.
+fn split_wall(wall: &Wall, points: &[Vec2]) -> Vec<Wall> {
+    let [start, end] = wall.points;
+    let ends: Vec<Vec2> = std::iter::once(start)
+        .chain(points.iter().copied())
+        .chain(std::iter::once(end))
+        .collect();
+    ends.windows(2)
+        .map(|piece| {
+            let skipped = start.distance(piece[0]);
+            let mut split = wall.clone();
+            split.points = [piece[0], piece[1]];
+            split.mapping = wall.mapping.continued(skipped);
+            split.upper.mapping = wall.upper.mapping.continued(skipped);
+            split.lower.mapping = wall.lower.mapping.continued(skipped);
+            split
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::rectangle;
+
+    fn wall(level: &Level, id: SectorId, start: (f32, f32), end: (f32, f32)) -> &Wall {
+        let points = [Vec2::from(start), Vec2::from(end)];
+        level
+            .sector(id)
+            .and_then(|sector| sector.walls.iter().find(|wall| wall.points == points))
+            .unwrap_or_else(|| panic!("no wall from {start:?} to {end:?}"))
+    }
+
+    #[test]
+    fn links_walls_shared_completely() {
+        let mut level = Level::default();
+        let left = level.add_sector(rectangle((0.0, 0.0), (10.0, 10.0)));
+        let right = level.add_sector(rectangle((10.0, 0.0), (20.0, 10.0)));
+
+        assert_eq!(level.link_portals(), 2);
+        assert_eq!(
+            wall(&level, left, (10.0, 10.0), (10.0, 0.0)).portal,
+            Some(right)
+        );
+        assert_eq!(
+            wall(&level, right, (10.0, 0.0), (10.0, 10.0)).portal,
+            Some(left)
+        );
+        assert!(level.sectors.iter().all(|sector| sector.walls.len() == 4));
+        // Nothing left to link the second time
+        assert_eq!(level.link_portals(), 0);
+    }
+
+    #[test]
+    fn splits_a_wall_shared_in_part() {
+        let mut level = Level::default();
+        let tall = level.add_sector(rectangle((0.0, 0.0), (10.0, 20.0)));
+        let short = level.add_sector(rectangle((10.0, 0.0), (20.0, 10.0)));
+
+        assert_eq!(level.link_portals(), 2);
+        assert_eq!(level.sector(tall).unwrap().walls.len(), 5);
+        assert_eq!(level.sector(short).unwrap().walls.len(), 4);
+        assert_eq!(wall(&level, tall, (10.0, 20.0), (10.0, 10.0)).portal, None);
+        assert_eq!(
+            wall(&level, tall, (10.0, 10.0), (10.0, 0.0)).portal,
+            Some(short)
+        );
+        assert_eq!(
+            wall(&level, short, (10.0, 0.0), (10.0, 10.0)).portal,
+            Some(tall)
+        );
+    }
+
+    #[test]
+    fn splits_both_walls_of_an_offset_overlap() {
+        let mut level = Level::default();
+        let low = level.add_sector(rectangle((0.0, 0.0), (10.0, 20.0)));
+        let high = level.add_sector(rectangle((10.0, 10.0), (20.0, 30.0)));
+
+        assert_eq!(level.link_portals(), 2);
+        assert_eq!(level.sector(low).unwrap().walls.len(), 5);
+        assert_eq!(level.sector(high).unwrap().walls.len(), 5);
+        assert_eq!(
+            wall(&level, low, (10.0, 20.0), (10.0, 10.0)).portal,
+            Some(high)
+        );
+        assert_eq!(
+            wall(&level, high, (10.0, 10.0), (10.0, 20.0)).portal,
+            Some(low)
+        );
+        assert_eq!(wall(&level, low, (10.0, 10.0), (10.0, 0.0)).portal, None);
+        assert_eq!(wall(&level, high, (10.0, 20.0), (10.0, 30.0)).portal, None);
+    }
+
+    #[test]
+    fn split_pieces_keep_the_texture_in_place() {
+        let mut level = Level::default();
+        let mut tall = rectangle((0.0, 0.0), (10.0, 20.0));
+        for wall in tall.walls.iter_mut() {
+            wall.mapping.offset = Vec2::new(3.0, 1.0);
+            wall.upper.mapping.offset.x = 5.0;
+        }
+        let tall = level.add_sector(tall);
+        level.add_sector(rectangle((10.0, 0.0), (20.0, 10.0)));
+        level.link_portals();
+
+        let first = wall(&level, tall, (10.0, 20.0), (10.0, 10.0));
+        let second = wall(&level, tall, (10.0, 10.0), (10.0, 0.0));
+        assert_eq!(first.mapping.offset, Vec2::new(3.0, 1.0));
+        assert_eq!(second.mapping.offset, Vec2::new(13.0, 1.0));
+        assert_eq!(first.upper.mapping.offset.x, 5.0);
+        assert_eq!(second.upper.mapping.offset.x, 15.0);
+    }
+}
@@ -0,0 +1,205 @@
+//! Reading and writing levels as [RON](https://github.com/ron-rs/ron) text.
+//!
+//! A level file holds the sectors of a [`Level`], each with its id, walls, heights and colors.
+//! Walls that open into another sector name it by id in `portal`. Anything worked out again
+//! when loading, like the next free sector id, is left out. A small level looks like this:
+//!
+//! ```ron
+//! Level(
+//!     sectors: [
+//!         Sector(
+//!             id: 0,
+//!             walls: [
+//!                 Wall(
+//!                     points: (Vec2(0.0, 0.0), Vec2(0.0, 40.0)),
+//!                     color: PixColor(128, 128, 128, 255),
+//!                     // Optional, in world units and starting from the floor when left out
+//!                     mapping: TextureMapping(
+//!                         offset: Vec2(0.0, 0.0),
+//!                         scale: Vec2(1.0, 1.0),
+//!                         peg: Bottom,
+//!                     ),
+//!                     // Optional, an image from the texture folder drawn over the color
+//!                     texture: Some("Bricks_01-128x128.png"),
+//!                     // Optional, brightens the wall when above zero and darkens it below
+//!                     light: -0.2,
+//!                     portal: None,
+//!                 ),
+//!                 Wall(
+//!                     points: (Vec2(0.0, 40.0), Vec2(40.0, 40.0)),
+//!                     color: PixColor(128, 128, 128, 255),
+//!                     // The wall opens into sector 1
+//!                     portal: Some(1),
+//!                     // Optional, white and untextured when left out
+//!                     upper: WallTexture(color: PixColor(90, 90, 90, 255)),
+//!                     lower: WallTexture(color: PixColor(90, 90, 90, 255)),
+//!                 ),
+//!                 // ...
+//!             ],
+//!             roof: 30.0,
+//!             floor: 0.0,
+//!             roof_col: PixColor(0, 0, 255, 255),
+//!             floor_col: PixColor(0, 255, 0, 255),
+//!             // Optional like the texture of a wall
+//!             floor_texture: Some("Bricks_01-128x128.png"),
+//!             // Optional, fully lit when left out
+//!             light: 0.8,
+//!         ),
+//!     ],
+//! )
+//! ```
+//!
+//! Map points are `Vec2(x, z)` and every wall runs from its first point to its second.
+//! Sectors whose walls run counter-clockwise are turned around when loaded.
+
+use std::{fmt, fs, io, path::Path};
+
+use ron::ser::PrettyConfig;
+
+use crate::define::Level;
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Write(ron::Error),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Io(error) => write!(f, "could not access level file: {error}"),
+            LevelError::Parse(error) => write!(f, "could not read level: {error}"),
+            LevelError::Write(error) => write!(f, "could not write level: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LevelError::Io(error) => Some(error),
+            LevelError::Parse(error) => Some(error),
+            LevelError::Write(error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for LevelError {
+    fn from(error: io::Error) -> Self {
+        LevelError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for LevelError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        LevelError::Parse(error)
+    }
+}
+
+impl From<ron::Error> for LevelError {
+    fn from(error: ron::Error) -> Self {
+        LevelError::Write(error)
+    }
+}
+
+impl Level {
+    pub fn from_ron(text: &str) -> Result<Self, LevelError> {
+        let mut level: Level = ron::from_str(text)?;
+        // Carry on handing out ids after the highest one in the file
+        level.next_id = level
+            .sectors
+            .iter()
+            .map(|sector| sector.id.0 + 1)
+            .max()
+            .unwrap_or_default();
+        // Files written by hand or older editors may have walls running either way
+        for sector in level.sectors.iter_mut() {
+            sector.normalize_winding();
+        }
+        Ok(level)
+    }
+
+    pub fn to_ron(&self) -> Result<String, LevelError> {
+        let config = PrettyConfig::new().struct_names(true);
+        Ok(ron::ser::to_string_pretty(self, config)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LevelError> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), LevelError> {
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        define::{PixColor, SectorId, TextureMapping},
+        test_util::rectangle,
+    };
+
+    #[test]
+    fn round_trips_a_level() {
+        let mut level = Level::default();
+        let first = level.next_id();
+        let second = SectorId(first.0 + 1);
+        let mut room = rectangle((0.0, 0.0), (10.0, 10.0));
+        room.walls[2].portal = Some(second);
+        room.walls[2].upper.color = PixColor(90, 90, 90, 255);
+        level.add_sector(room);
+        let mut hall = rectangle((10.0, 0.0), (20.0, 10.0));
+        hall.walls[0].portal = Some(first);
+        hall.floor = 2.0;
+        level.add_sector(hall);
+
+        let text = level.to_ron().unwrap();
+        let loaded = Level::from_ron(&text).unwrap();
+        assert_eq!(loaded.to_ron().unwrap(), text);
+        assert_eq!(loaded.next_id(), level.next_id());
+    }
+
+    #[test]
+    fn reads_a_level_without_the_optional_fields() {
+        let level = Level::from_ron(
+            "Level(
+                sectors: [
+                    Sector(
+                        id: 4,
+                        walls: [
+                            Wall(
+                                points: (Vec2(0.0, 0.0), Vec2(0.0, 40.0)),
+                                color: PixColor(128, 128, 128, 255),
+                            ),
+                            Wall(
+                                points: (Vec2(0.0, 40.0), Vec2(40.0, 0.0)),
+                                color: PixColor(128, 128, 128, 255),
+                                portal: Some(1),
+                            ),
+                            Wall(
+                                points: (Vec2(40.0, 0.0), Vec2(0.0, 0.0)),
+                                color: PixColor(128, 128, 128, 255),
+                            ),
+                        ],
+                        roof: 30.0,
+                        floor: 0.0,
+                        roof_col: PixColor(0, 0, 255, 255),
+                        floor_col: PixColor(0, 255, 0, 255),
+                    ),
+                ],
+            )",
+        )
+        .unwrap();
+        let sector = level.sector(SectorId(4)).unwrap();
+        assert_eq!(sector.walls.len(), 3);
+        assert_eq!(sector.walls[0].portal, None);
+        assert_eq!(sector.walls[1].portal, Some(SectorId(1)));
+        assert_eq!(sector.walls[0].mapping, TextureMapping::default());
+        assert_eq!(sector.walls[1].upper.mapping, TextureMapping::default());
+        assert_eq!(level.next_id(), SectorId(5));
+    }
+}
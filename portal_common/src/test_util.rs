@@ -0,0 +1,24 @@
+//! Sectors for the unit tests to build levels from
+
+use bevy::prelude::Vec2;
+
+use crate::define::{PixColor, Sector};
+
+/// A sector with walls running through the points in order and back to the first
+pub fn polygon(points: &[(f32, f32)]) -> Sector {
+    let mut sector = Sector::new(0.0, 10.0);
+    for (index, point) in points.iter().enumerate() {
+        let next = points[(index + 1) % points.len()];
+        sector.add_wall(
+            Vec2::from(*point),
+            Vec2::from(next),
+            PixColor(255, 255, 255, 255),
+        );
+    }
+    sector
+}
+
+/// Clockwise rectangle between two corners
+pub fn rectangle(min: (f32, f32), max: (f32, f32)) -> Sector {
+    polygon(&[min, (min.0, max.1), max, (max.0, min.1)])
+}
@@ -0,0 +1,98 @@
+//! Images that walls, floors and roofs refer to by name
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+
+use crate::define::{Level, TextureId};
+
+/// Folder inside the assets folder that texture names are looked up in
+pub const TEXTURE_FOLDER: &str = "textures";
+
+/// Every texture that is known by name, shared by the renderer and the editor
+#[derive(Resource, Default)]
+pub struct TextureRegistry {
+    // Sorted so lists of textures keep their order
+    textures: BTreeMap<TextureId, Handle<Image>>,
+}
+
+impl TextureRegistry {
+    pub fn get(&self, id: &TextureId) -> Option<&Handle<Image>> {
+        self.textures.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TextureId, &Handle<Image>)> {
+        self.textures.iter()
+    }
+
+    /// Starts loading the texture unless it is known already
+    pub fn load(&mut self, asset_server: &AssetServer, id: &TextureId) -> Handle<Image> {
+        self.textures
+            .entry(id.clone())
+            .or_insert_with(|| asset_server.load(format!("{TEXTURE_FOLDER}/{}", id.0)))
+            .clone()
+    }
+
+    /// Loads every texture the level names, including ones missing from the texture folder
+    pub fn load_level(&mut self, asset_server: &AssetServer, level: &Level) {
+        for sector in level.sectors.iter() {
+            let walls = sector
+                .walls
+                .iter()
+                .flat_map(|wall| [&wall.texture, &wall.upper.texture, &wall.lower.texture]);
+            for id in [&sector.floor_texture, &sector.roof_texture]
+                .into_iter()
+                .chain(walls)
+                .flatten()
+            {
+                self.load(asset_server, id);
+            }
+        }
+    }
+
+    /// Registers every image in the texture folder under its path inside the folder
+    pub fn load_folder(&mut self, asset_server: &AssetServer) {
+        let handles = match asset_server.load_folder(TEXTURE_FOLDER) {
+            Ok(handles) => handles,
+            Err(error) => {
+                warn!("could not load textures: {error}");
+                return;
+            }
+        };
+        for handle in handles {
+            let Some(path) = asset_server.get_handle_path(&handle) else {
+                continue;
+            };
+            let Ok(name) = path.path().strip_prefix(TEXTURE_FOLDER) else {
+                continue;
+            };
+            let id = TextureId(name.to_string_lossy().replace('\\', "/"));
+            self.textures.insert(id, handle.typed());
+        }
+    }
+}
+
+/// Fills the [`TextureRegistry`] from the texture folder and with whatever loaded levels name
+pub struct TexturePlugin;
+
+impl Plugin for TexturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TextureRegistry>()
+            .add_startup_system(load_texture_folder)
+            .add_system(load_level_textures);
+    }
+}
+
+fn load_texture_folder(asset_server: Res<AssetServer>, mut registry: ResMut<TextureRegistry>) {
+    registry.load_folder(&asset_server);
+}
+
+fn load_level_textures(
+    asset_server: Res<AssetServer>,
+    mut registry: ResMut<TextureRegistry>,
+    levels: Query<&Level, Changed<Level>>,
+) {
+    for level in levels.iter() {
+        registry.load_level(&asset_server, level);
+    }
+}
@@ -0,0 +1,299 @@
+//! Finding geometry the renderer can't draw before it ends up in a level file
+
+use std::fmt;
+
+use bevy::prelude::Vec2;
+
+use crate::{
+    define::{Level, Sector, SectorId},
+    geometry::{segment_intersection, Winding},
+};
+
+/// How far a point has to be from a sectors walls to count as inside it
+const OVERLAP_MARGIN: f32 = 1e-3;
+
+/// Something wrong with a sector or one of its walls
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub sector: SectorId,
+    /// Index of the wall at fault, `None` when it is the sector as a whole
+    pub wall: Option<usize>,
+    pub kind: DiagnosticKind,
+    /// Map position to look at to find the problem
+    pub position: Vec2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiagnosticKind {
+    /// Less than three walls can't enclose anything
+    TooFewWalls,
+    /// The roof is at or below the floor
+    NoHeight,
+    ZeroLengthWall,
+    /// The wall doesn't end where the next one starts
+    Gap,
+    /// The wall crosses another wall of the same sector
+    SelfIntersection {
+        wall: usize,
+    },
+    /// The walls run counter-clockwise so they face out of the sector
+    WrongWinding,
+    /// Part of the sector lies inside another one
+    Overlap {
+        sector: SectorId,
+    },
+    /// The portal leads into a sector that isn't in the level
+    MissingNeighbour {
+        neighbour: SectorId,
+    },
+    /// The neighbour has no portal running back along this wall
+    UnmatchedPortal {
+        neighbour: SectorId,
+    },
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::TooFewWalls => write!(f, "has less than three walls"),
+            DiagnosticKind::NoHeight => write!(f, "roof is not above the floor"),
+            DiagnosticKind::ZeroLengthWall => write!(f, "has no length"),
+            DiagnosticKind::Gap => write!(f, "doesn't end where the next wall starts"),
+            DiagnosticKind::SelfIntersection { wall } => write!(f, "crosses wall {wall}"),
+            DiagnosticKind::WrongWinding => write!(f, "walls run counter-clockwise"),
+            DiagnosticKind::Overlap { sector } => write!(f, "overlaps sector {}", sector.0),
+            DiagnosticKind::MissingNeighbour { neighbour } => {
+                write!(f, "portal leads to missing sector {}", neighbour.0)
+            }
+            DiagnosticKind::UnmatchedPortal { neighbour } => {
+                write!(f, "sector {} has no portal back", neighbour.0)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.wall {
+            Some(wall) => write!(f, "Sector {} wall {wall} {}", self.sector.0, self.kind),
+            None => write!(f, "Sector {} {}", self.sector.0, self.kind),
+        }
+    }
+}
+
+impl Level {
+    /// Every problem with the level that would break drawing it, empty when it is fine
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for sector in self.sectors.iter() {
+            validate_sector(sector, &mut diagnostics);
+            self.validate_portals(sector, &mut diagnostics);
+        }
+        for (index, sector) in self.sectors.iter().enumerate() {
+            for other in self.sectors[index + 1..].iter() {
+                if let Some(position) = overlap(sector, other) {
+                    diagnostics.push(Diagnostic {
+                        sector: sector.id,
+                        wall: None,
+                        kind: DiagnosticKind::Overlap { sector: other.id },
+                        position,
+                    });
+                }
+            }
+        }
+        diagnostics
+    }
+
+    fn validate_portals(&self, sector: &Sector, diagnostics: &mut Vec<Diagnostic>) {
+        for (index, wall) in sector.walls.iter().enumerate() {
+            let Some(neighbour) = wall.portal else {
+                continue;
+            };
+            let kind = match self.sector(neighbour) {
+                None => DiagnosticKind::MissingNeighbour { neighbour },
+                Some(other)
+                    if !other.walls.iter().any(|other_wall| {
+                        other_wall.portal == Some(sector.id) && other_wall.is_reverse_of(wall)
+                    }) =>
+                {
+                    DiagnosticKind::UnmatchedPortal { neighbour }
+                }
+                Some(_) => continue,
+            };
+            diagnostics.push(Diagnostic {
+                sector: sector.id,
+                wall: Some(index),
+                kind,
+                position: (wall.points[0] + wall.points[1]) / 2.0,
+            });
+        }
+    }
+}
+
+fn validate_sector(sector: &Sector, diagnostics: &mut Vec<Diagnostic>) {
+    let middle = middle(sector);
+    let mut push = |wall, kind, position| {
+        diagnostics.push(Diagnostic {
+            sector: sector.id,
+            wall,
+            kind,
+            position,
+        })
+    };
+
+    if sector.walls.len() < 3 {
+        push(None, DiagnosticKind::TooFewWalls, middle);
+    }
+    if sector.roof <= sector.floor {
+        push(None, DiagnosticKind::NoHeight, middle);
+    }
+
+    let walls = &sector.walls;
+    for (index, wall) in walls.iter().enumerate() {
+        let [start, end] = wall.points;
+        if start == end {
+            push(Some(index), DiagnosticKind::ZeroLengthWall, start);
+        }
+        if end != walls[(index + 1) % walls.len()].points[0] {
+            push(Some(index), DiagnosticKind::Gap, end);
+        }
+        for (other_index, other) in walls.iter().enumerate().skip(index + 1) {
+            if let Some(position) = segment_intersection(wall.points, other.points) {
+                let kind = DiagnosticKind::SelfIntersection { wall: other_index };
+                push(Some(index), kind, position);
+            }
+        }
+    }
+
+    if walls.len() >= 3 && sector.winding() == Some(Winding::CounterClockwise) {
+        push(None, DiagnosticKind::WrongWinding, middle);
+    }
+}
+
+/// Average of the wall ends, close enough to the middle to point at a sector
+fn middle(sector: &Sector) -> Vec2 {
+    let sum: Vec2 = sector.walls.iter().map(|wall| wall.points[0]).sum();
+    sum / sector.walls.len().max(1) as f32
+}
+
+/// A point where the two sectors overlap, if they do
+fn overlap(first: &Sector, second: &Sector) -> Option<Vec2> {
+    for wall in first.walls.iter() {
+        for other in second.walls.iter() {
+            if let Some(position) = segment_intersection(wall.points, other.points) {
+                return Some(position);
+            }
+        }
+    }
+    // Without crossing walls one can still sit completely inside the other,
+    // sharing walls is fine so only points clear of the other sectors walls count
+    let strictly_inside = |sector: &Sector, point: Vec2| {
+        sector.contains(point)
+            && sector
+                .walls
+                .iter()
+                .all(|wall| wall.distance_to(point) > OVERLAP_MARGIN)
+    };
+    let probes = |sector: &Sector| {
+        sector
+            .walls
+            .iter()
+            .flat_map(|wall| [wall.points[0], (wall.points[0] + wall.points[1]) / 2.0])
+            // Catches two copies of the same sector
+            .chain([middle(sector)])
+            .collect::<Vec<_>>()
+    };
+    probes(first)
+        .into_iter()
+        .find(|point| strictly_inside(second, *point))
+        .or_else(|| {
+            probes(second)
+                .into_iter()
+                .find(|point| strictly_inside(first, *point))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{polygon, rectangle};
+
+    fn kinds(level: &Level) -> Vec<DiagnosticKind> {
+        level
+            .validate()
+            .into_iter()
+            .map(|diagnostic| diagnostic.kind)
+            .collect()
+    }
+
+    #[test]
+    fn linked_neighbours_are_fine() {
+        let mut level = Level::default();
+        let room = level.next_id();
+        let hall = SectorId(room.0 + 1);
+        let mut sector = rectangle((0.0, 0.0), (10.0, 10.0));
+        sector.walls[2].portal = Some(hall);
+        level.add_sector(sector);
+        let mut sector = rectangle((10.0, 0.0), (20.0, 10.0));
+        sector.walls[0].portal = Some(room);
+        level.add_sector(sector);
+        assert_eq!(kinds(&level), []);
+    }
+
+    #[test]
+    fn finds_crossing_walls() {
+        let mut level = Level::default();
+        let bowtie = level.add_sector(polygon(&[
+            (0.0, 0.0),
+            (0.0, 10.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+        ]));
+        let diagnostics = level.validate();
+        let crossing = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.kind == DiagnosticKind::SelfIntersection { wall: 3 })
+            .expect("the crossing walls are reported");
+        assert_eq!(crossing.sector, bowtie);
+        assert_eq!(crossing.wall, Some(1));
+        assert!(crossing.position.distance(Vec2::new(5.0, 5.0)) < 1e-4);
+    }
+
+    #[test]
+    fn finds_overlapping_sectors() {
+        let mut level = Level::default();
+        let first = level.add_sector(rectangle((0.0, 0.0), (10.0, 10.0)));
+        let second = level.add_sector(rectangle((5.0, 5.0), (15.0, 15.0)));
+        let diagnostics = level.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].sector, first);
+        assert_eq!(
+            diagnostics[0].kind,
+            DiagnosticKind::Overlap { sector: second }
+        );
+    }
+
+    #[test]
+    fn finds_a_sector_inside_another() {
+        let mut level = Level::default();
+        let outer = level.add_sector(rectangle((0.0, 0.0), (20.0, 20.0)));
+        level.add_sector(rectangle((5.0, 5.0), (10.0, 10.0)));
+        assert!(matches!(
+            kinds(&level)[..],
+            [DiagnosticKind::Overlap { sector }] if sector != outer
+        ));
+    }
+
+    #[test]
+    fn finds_walls_facing_out() {
+        let mut level = Level::default();
+        // Pushed directly, add_sector would turn it around
+        level.sectors.push(polygon(&[
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (0.0, 10.0),
+        ]));
+        assert_eq!(kinds(&level), [DiagnosticKind::WrongWinding]);
+    }
+}
@@ -0,0 +1,123 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+
+use crate::{EditMode, EditingLevel, EditorState};
+
+/// How far the camera can zoom in and out
+const MIN_SCALE: f32 = 0.02;
+const MAX_SCALE: f32 = 20.0;
+/// Scale change for one line of scrolling
+const ZOOM_STEP: f32 = 1.1;
+/// Room left around the level by frame all
+const FRAME_MARGIN: f32 = 1.1;
+
+/// Sent to fit the whole level on screen
+pub struct FrameAll;
+
+/// Sent to center the view on a map position
+pub struct FocusOn(pub Vec2);
+
+pub fn move_camera(
+    mut contexts: EguiContexts,
+    // Cursor position of the last frame while the camera is being dragged
+    mut drag: Local<Option<Vec2>>,
+    (mut scroll_events, mut frame_events, mut focus_events): (
+        EventReader<MouseWheel>,
+        EventReader<FrameAll>,
+        EventReader<FocusOn>,
+    ),
+    (editor_state, level): (Res<EditorState>, Res<EditingLevel>),
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    (buttons, keys): (Res<Input<MouseButton>>, Res<Input<KeyCode>>),
+) {
+    let (Ok(window), Ok((mut transform, mut projection))) =
+        (windows.get_single(), camera_q.get_single_mut())
+    else {
+        return;
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+    let over_ui = contexts.ctx_mut().is_pointer_over_area();
+    // An F typed into a text field is meant for the text
+    let typing = contexts.ctx_mut().wants_keyboard_input();
+
+    if frame_events.iter().count() > 0 || (keys.just_pressed(KeyCode::F) && !over_ui && !typing) {
+        frame_all(&level, window_size, &mut transform, &mut projection);
+    }
+    if let Some(FocusOn(position)) = focus_events.iter().last() {
+        transform.translation = position.extend(transform.translation.z);
+    }
+
+    let Some(cursor) = window.cursor_position() else {
+        *drag = None;
+        return;
+    };
+    // Where the cursor is relative to the camera in world units
+    let cursor_offset = (cursor - window_size / 2.0) * projection.scale;
+
+    // Middle mouse always drags, left only while panning
+    let drag_button = buttons.pressed(MouseButton::Middle)
+        || (editor_state.mode == EditMode::Pan && buttons.pressed(MouseButton::Left));
+    let drag_started = buttons.just_pressed(MouseButton::Middle)
+        || (editor_state.mode == EditMode::Pan && buttons.just_pressed(MouseButton::Left));
+    if drag_started && !over_ui {
+        *drag = Some(cursor);
+    } else if !drag_button {
+        *drag = None;
+    }
+
+    if let Some(last) = *drag {
+        // Keep the point that was grabbed under the cursor
+        transform.translation -= ((cursor - last) * projection.scale).extend(0.0);
+        *drag = Some(cursor);
+    }
+
+    if over_ui {
+        scroll_events.clear();
+        return;
+    }
+    let scroll: f32 = scroll_events
+        .iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 20.0,
+        })
+        .sum();
+    if scroll != 0.0 {
+        let old_scale = projection.scale;
+        projection.scale = (old_scale * ZOOM_STEP.powf(-scroll)).clamp(MIN_SCALE, MAX_SCALE);
+
+        // Zoom around the cursor rather than the middle of the screen
+        let cursor_world = transform.translation.truncate() + cursor_offset;
+        let new_offset = cursor_offset * projection.scale / old_scale;
+        transform.translation = (cursor_world - new_offset).extend(transform.translation.z);
+    }
+}
+
+fn frame_all(
+    level: &EditingLevel,
+    window_size: Vec2,
+    transform: &mut Transform,
+    projection: &mut OrthographicProjection,
+) {
+    let mut points = level
+        .0
+        .sectors
+        .iter()
+        .flat_map(|sector| sector.walls.iter())
+        .flat_map(|wall| wall.points);
+    let Some(first) = points.next() else {
+        return;
+    };
+    let (min, max) = points.fold((first, first), |(min, max), point| {
+        (min.min(point), max.max(point))
+    });
+
+    let size = (max - min) * FRAME_MARGIN / window_size;
+    projection.scale = size.max_element().clamp(MIN_SCALE, MAX_SCALE);
+    transform.translation = ((min + max) / 2.0).extend(transform.translation.z);
+}
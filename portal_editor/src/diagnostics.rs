@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use portal_common::prelude::*;
+
+use crate::{camera::FocusOn, pick::Pick, select::Selection, EditingLevel};
+
+/// What is wrong with the level, checked again only when the level changes
+#[derive(Resource, Default)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+pub fn update_diagnostics(level: Res<EditingLevel>, mut diagnostics: ResMut<Diagnostics>) {
+    if level.is_changed() {
+        diagnostics.0 = level.0.validate();
+    }
+}
+
+/// Lists what is wrong with the level, clicking a problem selects it and moves the view there
+pub fn diagnostics_ui(
+    mut contexts: EguiContexts,
+    diagnostics: Res<Diagnostics>,
+    mut selection: ResMut<Selection>,
+    mut focus_events: EventWriter<FocusOn>,
+) {
+    let diagnostics = &diagnostics.0;
+    egui::TopBottomPanel::bottom("Diagnostics").show(contexts.ctx_mut(), |ui| {
+        if diagnostics.is_empty() {
+            ui.label("No problems found");
+            return;
+        }
+        ui.label(format!("{} problems", diagnostics.len()));
+        egui::ScrollArea::vertical()
+            .max_height(120.0)
+            .show(ui, |ui| {
+                for diagnostic in diagnostics.iter() {
+                    if ui.link(diagnostic.to_string()).clicked() {
+                        selection.items = vec![match diagnostic.wall {
+                            Some(index) => Pick::Wall(diagnostic.sector, index),
+                            None => Pick::Sector(diagnostic.sector),
+                        }];
+                        focus_events.send(FocusOn(diagnostic.position));
+                    }
+                }
+            });
+    });
+}
@@ -0,0 +1,175 @@
+use bevy::{app::AppExit, prelude::*, window::WindowCloseRequested};
+use bevy_egui::{egui, EguiContexts};
+use portal_common::prelude::*;
+
+use crate::{history::History, select::Selection, EditingLevel, EditorState};
+
+/// Where the level being edited is saved, whether it changed since is kept by [`History`]
+#[derive(Resource)]
+pub struct LevelFile {
+    /// Path typed into the menu bar, used by Open and Save As
+    pub path: String,
+    /// File the level was last opened from or saved to
+    pub saved_path: Option<String>,
+    /// Waiting on the user to confirm throwing away unsaved changes
+    pending: Option<FileAction>,
+    status: String,
+}
+
+impl Default for LevelFile {
+    fn default() -> Self {
+        Self {
+            path: String::from("untitled.level"),
+            saved_path: None,
+            pending: None,
+            status: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FileAction {
+    New,
+    Open,
+    Save,
+    SaveAs,
+    Quit,
+}
+
+impl FileAction {
+    /// Whether running this loses the level being edited
+    fn discards(self) -> bool {
+        matches!(self, FileAction::New | FileAction::Open | FileAction::Quit)
+    }
+}
+
+pub fn file_ui(
+    mut contexts: EguiContexts,
+    mut file: ResMut<LevelFile>,
+    mut level: ResMut<EditingLevel>,
+    (mut editor_state, mut selection, mut history): (
+        ResMut<EditorState>,
+        ResMut<Selection>,
+        ResMut<History>,
+    ),
+    mut exit: EventWriter<AppExit>,
+    (mut textures, asset_server): (ResMut<TextureRegistry>, Res<AssetServer>),
+) {
+    let mut action = None;
+    // Set once the user agreed to lose their changes
+    let mut confirmed = false;
+    let ctx = contexts.ctx_mut();
+
+    egui::TopBottomPanel::top("File").show(ctx, |ui| {
+        egui::menu::bar(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                for (label, file_action) in [
+                    ("New", FileAction::New),
+                    ("Open", FileAction::Open),
+                    ("Save", FileAction::Save),
+                    ("Save As", FileAction::SaveAs),
+                    ("Quit", FileAction::Quit),
+                ] {
+                    if ui.button(label).clicked() {
+                        action = Some(file_action);
+                        ui.close_menu();
+                    }
+                }
+            });
+            ui.label("Path");
+            ui.text_edit_singleline(&mut file.path);
+            if history.is_dirty() {
+                ui.label("(unsaved changes)");
+            }
+            ui.label(&file.status);
+        });
+    });
+
+    // Ask before throwing away work that hasn't been saved
+    if let Some(pending) = file.pending {
+        egui::Window::new("Unsaved changes")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("The level has unsaved changes, discard them?");
+                ui.horizontal(|ui| {
+                    if ui.button("Discard").clicked() {
+                        file.pending = None;
+                        action = Some(pending);
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        file.pending = None;
+                    }
+                });
+            });
+    }
+
+    let Some(action) = action else {
+        return;
+    };
+    if action.discards() && history.is_dirty() && !confirmed {
+        file.pending = Some(action);
+        return;
+    }
+
+    match action {
+        FileAction::New => {
+            level.0 = Level::default();
+            editor_state.points.clear();
+            selection.items.clear();
+            history.clear();
+            file.saved_path = None;
+            file.status = String::from("New level");
+        }
+        FileAction::Open => match Level::load(&file.path) {
+            Ok(loaded) => {
+                // Textures outside the texture folder still get a thumbnail
+                textures.load_level(&asset_server, &loaded);
+                level.0 = loaded;
+                editor_state.points.clear();
+                selection.items.clear();
+                history.clear();
+                file.saved_path = Some(file.path.clone());
+                file.status = format!("Opened {}", file.path);
+            }
+            Err(error) => file.status = error.to_string(),
+        },
+        FileAction::Save | FileAction::SaveAs => {
+            // Save goes back to where the level came from, Save As to the typed path
+            let path = match (&file.saved_path, action) {
+                (Some(saved_path), FileAction::Save) => saved_path.clone(),
+                _ => file.path.clone(),
+            };
+            match level.0.save(&path) {
+                Ok(()) => {
+                    history.mark_saved();
+                    file.status = match level.0.validate().len() {
+                        0 => format!("Saved {path}"),
+                        problems => format!("Saved {path} with {problems} problems"),
+                    };
+                    file.path = path.clone();
+                    file.saved_path = Some(path);
+                }
+                Err(error) => file.status = error.to_string(),
+            }
+        }
+        FileAction::Quit => exit.send(AppExit),
+    }
+}
+
+/// Closing the window goes through Quit so unsaved changes get a warning first
+pub fn handle_close_requests(
+    mut events: EventReader<WindowCloseRequested>,
+    mut file: ResMut<LevelFile>,
+    history: Res<History>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if events.iter().next().is_some() {
+        if history.is_dirty() {
+            file.pending = Some(FileAction::Quit);
+        } else {
+            exit.send(AppExit);
+        }
+    }
+}
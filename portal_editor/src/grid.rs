@@ -0,0 +1,69 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_prototype_debug_lines::DebugLines;
+
+use crate::EditorState;
+
+/// Every this many grid lines one is drawn brighter
+const MAJOR_EVERY: i32 = 8;
+/// Lines closer together than this many pixels are skipped so zooming out stays readable
+const MIN_SPACING: f32 = 6.0;
+const MINOR_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const MAJOR_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+
+pub fn draw_grid(
+    editor_state: Res<EditorState>,
+    mut lines: ResMut<DebugLines>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_q: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+) {
+    let (Ok(window), Ok((transform, projection))) = (windows.get_single(), camera_q.get_single())
+    else {
+        return;
+    };
+
+    // Part of the world that is on screen
+    let half_size = Vec2::new(window.width(), window.height()) / 2.0 * projection.scale;
+    let center = transform.translation.truncate();
+    let (min, max) = (center - half_size, center + half_size);
+
+    let minor = editor_state.grid_size;
+    let show_minor = minor / projection.scale >= MIN_SPACING;
+    let major = minor * MAJOR_EVERY as f32;
+    if major / projection.scale < MIN_SPACING {
+        return;
+    }
+
+    // Vertical lines
+    let first = (min.x / minor).floor() as i32;
+    let last = (max.x / minor).ceil() as i32;
+    for i in first..=last {
+        let is_major = i.rem_euclid(MAJOR_EVERY) == 0;
+        if is_major || show_minor {
+            let x = i as f32 * minor;
+            let color = if is_major { MAJOR_COLOR } else { MINOR_COLOR };
+            lines.line_colored(
+                Vec3::new(x, min.y, 0.0),
+                Vec3::new(x, max.y, 0.0),
+                0.0,
+                color,
+            );
+        }
+    }
+
+    // Horizontal lines
+    let first = (min.y / minor).floor() as i32;
+    let last = (max.y / minor).ceil() as i32;
+    for i in first..=last {
+        let is_major = i.rem_euclid(MAJOR_EVERY) == 0;
+        if is_major || show_minor {
+            let y = i as f32 * minor;
+            let color = if is_major { MAJOR_COLOR } else { MINOR_COLOR };
+            lines.line_colored(
+                Vec3::new(min.x, y, 0.0),
+                Vec3::new(max.x, y, 0.0),
+                0.0,
+                color,
+            );
+        }
+    }
+}
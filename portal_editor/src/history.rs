@@ -0,0 +1,311 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use portal_common::prelude::*;
+
+use crate::{select::Selection, EditingLevel, EditorState};
+
+/// A sector as it was before and after an edit, `None` when it didn't exist
+struct SectorChange {
+    before: Option<(usize, Sector)>,
+    after: Option<(usize, Sector)>,
+}
+
+/// One undoable step, remembering the state of everything it touched
+pub struct Edit {
+    label: String,
+    sectors: Vec<SectorChange>,
+    /// Points placed in Add mode before and after
+    points: Option<(Vec<Vec2>, Vec<Vec2>)>,
+    /// Folds into the previous edit while the drag that made it is still going on,
+    /// so dragging a value is one step
+    mergeable: bool,
+}
+
+impl Edit {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            sectors: Vec::new(),
+            points: None,
+            mergeable: false,
+        }
+    }
+
+    /// Records how the sectors differ between two states of the level
+    pub fn sectors(mut self, before: &Level, after: &Level) -> Self {
+        for (index, sector) in before.sectors.iter().enumerate() {
+            match after.index_of(sector.id) {
+                Some(after_index) if after.sectors[after_index] == *sector => {}
+                Some(after_index) => self.sectors.push(SectorChange {
+                    before: Some((index, sector.clone())),
+                    after: Some((after_index, after.sectors[after_index].clone())),
+                }),
+                None => self.sectors.push(SectorChange {
+                    before: Some((index, sector.clone())),
+                    after: None,
+                }),
+            }
+        }
+        for (index, sector) in after.sectors.iter().enumerate() {
+            if before.index_of(sector.id).is_none() {
+                self.sectors.push(SectorChange {
+                    before: None,
+                    after: Some((index, sector.clone())),
+                });
+            }
+        }
+        self
+    }
+
+    pub fn points(mut self, before: Vec<Vec2>, after: Vec<Vec2>) -> Self {
+        self.points = Some((before, after));
+        self
+    }
+
+    pub fn mergeable(mut self) -> Self {
+        self.mergeable = true;
+        self
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sectors.is_empty()
+            && self
+                .points
+                .as_ref()
+                .is_none_or(|(before, after)| before == after)
+    }
+
+    /// Takes on the end state of a later edit while keeping our start state
+    fn merge(&mut self, later: Edit) {
+        for change in later.sectors {
+            let id = change
+                .before
+                .as_ref()
+                .or(change.after.as_ref())
+                .map(|(_, sector)| sector.id);
+            let existing = self.sectors.iter_mut().find(|existing| {
+                existing
+                    .after
+                    .as_ref()
+                    .map(|(_, sector)| sector.id)
+                    .is_some_and(|existing_id| Some(existing_id) == id)
+            });
+            match existing {
+                Some(existing) => existing.after = change.after,
+                None => self.sectors.push(change),
+            }
+        }
+        if let Some((before, after)) = later.points {
+            let before = self.points.take().map_or(before, |(ours, _)| ours);
+            self.points = Some((before, after));
+        }
+    }
+
+    /// Puts the sectors back the way they were on one side of the edit
+    fn apply(&self, level: &mut Level, points: &mut Vec<Vec2>, undo: bool) {
+        let side = |change: &SectorChange| {
+            if undo {
+                (change.after.clone(), change.before.clone())
+            } else {
+                (change.before.clone(), change.after.clone())
+            }
+        };
+
+        let mut inserted = Vec::new();
+        for change in self.sectors.iter() {
+            match side(change) {
+                (Some((_, from)), None) => {
+                    if let Some(index) = level.index_of(from.id) {
+                        level.sectors.remove(index);
+                    }
+                }
+                (Some((_, from)), Some((_, to))) => {
+                    if let Some(sector) = level.sector_mut(from.id) {
+                        *sector = to;
+                    }
+                }
+                (None, Some(to)) => inserted.push(to),
+                (None, None) => {}
+            }
+        }
+        // Going from the front keeps every index right for the sectors after it
+        inserted.sort_by_key(|(index, _)| *index);
+        for (index, sector) in inserted {
+            let index = index.min(level.sectors.len());
+            level.sectors.insert(index, sector);
+        }
+
+        if let Some((before, after)) = self.points.as_ref() {
+            *points = if undo { before.clone() } else { after.clone() };
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct History {
+    edits: Vec<Edit>,
+    /// How many of the edits are applied, the ones after it can be redone
+    cursor: usize,
+    /// `cursor` at the last save, `None` once that state can't be reached anymore
+    saved: Option<usize>,
+    /// Whether the last edit is part of a drag that hasn't ended yet
+    merging: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            edits: Vec::new(),
+            cursor: 0,
+            saved: Some(0),
+            merging: false,
+        }
+    }
+}
+
+impl History {
+    pub fn push(&mut self, edit: Edit) {
+        if edit.is_empty() {
+            return;
+        }
+        // A new edit replaces whatever could have been redone
+        if self.saved.is_some_and(|saved| saved > self.cursor) {
+            self.saved = None;
+        }
+        self.edits.truncate(self.cursor);
+
+        if let Some(last) = self.edits.last_mut() {
+            if edit.mergeable
+                && self.merging
+                && last.label == edit.label
+                && self.saved != Some(self.cursor)
+            {
+                last.merge(edit);
+                return;
+            }
+        }
+        self.merging = edit.mergeable;
+        self.edits.push(edit);
+        self.cursor = self.edits.len();
+    }
+
+    /// Starts a new step for the next edit, for when a drag starts or ends
+    pub fn end_merging(&mut self) {
+        self.merging = false;
+    }
+
+    pub fn undo(&mut self, level: &mut Level, points: &mut Vec<Vec2>) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.cursor -= 1;
+        self.merging = false;
+        self.edits[self.cursor].apply(level, points, true);
+        true
+    }
+
+    pub fn redo(&mut self, level: &mut Level, points: &mut Vec<Vec2>) -> bool {
+        if self.cursor == self.edits.len() {
+            return false;
+        }
+        self.edits[self.cursor].apply(level, points, false);
+        self.cursor += 1;
+        self.merging = false;
+        true
+    }
+
+    /// Whether the level differs from the last time it was saved or opened
+    pub fn is_dirty(&self) -> bool {
+        let Some(saved) = self.saved else {
+            return true;
+        };
+        let range = saved.min(self.cursor)..saved.max(self.cursor);
+        // Placing points alone doesn't touch the level
+        self.edits[range]
+            .iter()
+            .any(|edit| !edit.sectors.is_empty())
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.cursor);
+    }
+
+    /// Forgets every edit, for when a different level gets loaded
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Lists the edits in the side panel, clicking one undoes or redoes up to it
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        level: &mut ResMut<EditingLevel>,
+        points: &mut Vec<Vec2>,
+        selection: &mut Selection,
+    ) {
+        let mut target = None;
+        ui.horizontal(|ui| {
+            if ui.button("Undo").clicked() {
+                target = self.cursor.checked_sub(1);
+            }
+            if ui.button("Redo").clicked() && self.cursor < self.edits.len() {
+                target = Some(self.cursor + 1);
+            }
+        });
+        egui::ScrollArea::vertical()
+            .id_source("History")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                if ui.selectable_label(self.cursor == 0, "Start").clicked() {
+                    target = Some(0);
+                }
+                for (index, edit) in self.edits.iter().enumerate() {
+                    if ui
+                        .selectable_label(self.cursor == index + 1, &edit.label)
+                        .clicked()
+                    {
+                        target = Some(index + 1);
+                    }
+                }
+            });
+
+        if let Some(target) = target {
+            // Only touched here so the level doesn't count as changed every frame
+            while self.cursor > target && self.undo(&mut level.0, points) {}
+            while self.cursor < target && self.redo(&mut level.0, points) {}
+            // Selected indices may not line up anymore
+            selection.items.clear();
+        }
+    }
+}
+
+/// Ctrl+Z undoes and Ctrl+Shift+Z or Ctrl+Y redoes
+pub fn undo_keys(
+    mut contexts: EguiContexts,
+    keys: Res<Input<KeyCode>>,
+    mut history: ResMut<History>,
+    mut level: ResMut<EditingLevel>,
+    mut editor_state: ResMut<EditorState>,
+    mut selection: ResMut<Selection>,
+) {
+    // Let text fields keep their own undo
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let ctrl = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    if !ctrl {
+        return;
+    }
+
+    let changed = if keys.just_pressed(KeyCode::Z) && !shift {
+        history.undo(&mut level.0, &mut editor_state.points)
+    } else if (keys.just_pressed(KeyCode::Z) && shift) || keys.just_pressed(KeyCode::Y) {
+        history.redo(&mut level.0, &mut editor_state.points)
+    } else {
+        false
+    };
+    if changed {
+        selection.items.clear();
+    }
+}
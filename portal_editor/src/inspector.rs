@@ -0,0 +1,279 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use portal_common::prelude::*;
+
+use crate::{
+    history::{Edit, History},
+    pick::Pick,
+    select::Selection,
+    EditingLevel,
+};
+
+/// Size of the textures shown next to their names, the picker shows them twice as big
+const THUMBNAIL_SIZE: f32 = 24.0;
+/// Textures per row of the picker
+const PICKER_COLUMNS: usize = 4;
+
+/// Edits the selected sector and its walls, changes go straight into the level
+pub fn inspector_ui(
+    mut contexts: EguiContexts,
+    selection: Res<Selection>,
+    mut level: ResMut<EditingLevel>,
+    mut history: ResMut<History>,
+    textures: Res<TextureRegistry>,
+) {
+    let Some(id) = selection.sector() else {
+        return;
+    };
+    // Work on a copy so the level only counts as changed when something was edited
+    let Some(mut sector) = level.0.sector(id).cloned() else {
+        return;
+    };
+    let selected_wall = selection.items.iter().find_map(|item| match item {
+        Pick::Wall(wall_sector, index) if *wall_sector == id => Some(*index),
+        _ => None,
+    });
+
+    let thumbnails: Vec<(TextureId, egui::TextureId)> = textures
+        .iter()
+        .map(|(id, handle)| (id.clone(), contexts.add_image(handle.clone_weak())))
+        .collect();
+
+    let mut changed = false;
+    let mut gesture = Gesture::default();
+    let mut align = None;
+    egui::SidePanel::right("Inspector").show(contexts.ctx_mut(), |ui| {
+        ui.heading(format!("Sector {}", id.0));
+        changed |= gesture.watch(
+            &ui.add(
+                egui::DragValue::new(&mut sector.floor)
+                    .speed(0.5)
+                    .prefix("Floor "),
+            ),
+        );
+        changed |= gesture.watch(
+            &ui.add(
+                egui::DragValue::new(&mut sector.roof)
+                    .speed(0.5)
+                    .prefix("Roof "),
+            ),
+        );
+        changed |= gesture.watch(
+            &ui.add(
+                egui::DragValue::new(&mut sector.light)
+                    .speed(0.01)
+                    .clamp_range(0.0..=1.0)
+                    .prefix("Light "),
+            ),
+        );
+        changed |= color_edit(ui, "Floor color", &mut sector.floor_col, &mut gesture);
+        changed |= color_edit(ui, "Roof color", &mut sector.roof_col, &mut gesture);
+        changed |= texture_edit(ui, "Floor texture", &mut sector.floor_texture, &thumbnails);
+        changed |= texture_edit(ui, "Roof texture", &mut sector.roof_texture, &thumbnails);
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, wall) in sector.walls.iter_mut().enumerate() {
+                let title = match wall.portal {
+                    Some(neighbour) => format!("Wall {index} (portal to {})", neighbour.0),
+                    None => format!("Wall {index}"),
+                };
+                egui::CollapsingHeader::new(title)
+                    .id_source((id, index))
+                    .default_open(selected_wall == Some(index))
+                    .show(ui, |ui| {
+                        changed |= color_edit(ui, "Color", &mut wall.color, &mut gesture);
+                        changed |= texture_edit(ui, "Texture", &mut wall.texture, &thumbnails);
+                        changed |= mapping_edit(ui, &mut wall.mapping, &mut gesture);
+                        changed |= gesture.watch(
+                            &ui.add(
+                                egui::DragValue::new(&mut wall.light)
+                                    .speed(0.01)
+                                    .clamp_range(-1.0..=1.0)
+                                    .prefix("Light "),
+                            )
+                            .on_hover_text("Added to the light of the sector"),
+                        );
+                        if ui
+                            .button("Align following walls")
+                            .on_hover_text("Carries the texture on around the corners")
+                            .clicked()
+                        {
+                            align = Some(index);
+                        }
+                        // Step walls only show up around portals
+                        if wall.is_portal() {
+                            ui.label("Upper");
+                            changed |= color_edit(ui, "Color", &mut wall.upper.color, &mut gesture);
+                            changed |=
+                                texture_edit(ui, "Texture", &mut wall.upper.texture, &thumbnails);
+                            changed |= mapping_edit(ui, &mut wall.upper.mapping, &mut gesture);
+                            ui.label("Lower");
+                            changed |= color_edit(ui, "Color", &mut wall.lower.color, &mut gesture);
+                            changed |=
+                                texture_edit(ui, "Texture", &mut wall.lower.texture, &thumbnails);
+                            changed |= mapping_edit(ui, &mut wall.lower.mapping, &mut gesture);
+                        }
+                    });
+            }
+        });
+    });
+
+    if let Some(index) = align {
+        let before = sector.clone();
+        sector.align_textures(index);
+        changed |= sector != before;
+    }
+
+    if gesture.started {
+        history.end_merging();
+    }
+    if changed {
+        let before = level.0.clone();
+        if let Some(target) = level.0.sector_mut(id) {
+            *target = sector;
+        }
+        // Dragging a value changes it every frame, those all become one step
+        let edit = Edit::new(format!("Edit sector {}", id.0)).sectors(&before, &level.0);
+        history.push(if gesture.dragging {
+            edit.mergeable()
+        } else {
+            edit
+        });
+    }
+    if gesture.released {
+        history.end_merging();
+    }
+}
+
+/// What the value widgets did this frame, so each drag becomes one undo step
+/// while clicks and typed values get one each
+#[derive(Default)]
+struct Gesture {
+    /// A drag began, its edits start a new step
+    started: bool,
+    /// A value is being dragged, its edits fold into the step the drag started
+    dragging: bool,
+    /// A drag ended, later edits start a new step
+    released: bool,
+}
+
+impl Gesture {
+    /// Notes what a widget is doing and returns whether it changed its value
+    fn watch(&mut self, response: &egui::Response) -> bool {
+        self.started |= response.drag_started();
+        self.dragging |= response.dragged();
+        self.released |= response.drag_released();
+        response.changed()
+    }
+
+    /// Colors are dragged around inside a popup, which their button only reports as changes
+    fn watch_popup(&mut self, ui: &egui::Ui, response: &egui::Response) -> bool {
+        let (pressed, down, released) = ui.input(|input| {
+            let pointer = &input.pointer;
+            (
+                pointer.primary_pressed(),
+                pointer.primary_down(),
+                pointer.primary_released(),
+            )
+        });
+        self.started |= pressed;
+        self.dragging |= response.changed() && down;
+        self.released |= released;
+        response.changed()
+    }
+}
+
+fn color_edit(ui: &mut egui::Ui, label: &str, color: &mut PixColor, gesture: &mut Gesture) -> bool {
+    let mut rgba = [color.0, color.1, color.2, color.3];
+    let changed = ui
+        .horizontal(|ui| {
+            ui.label(label);
+            let response = ui.color_edit_button_srgba_unmultiplied(&mut rgba);
+            gesture.watch_popup(ui, &response)
+        })
+        .inner;
+    *color = PixColor(rgba[0], rgba[1], rgba[2], rgba[3]);
+    changed
+}
+
+/// Offset and scale of a texture in world units and the edge it starts from
+fn mapping_edit(ui: &mut egui::Ui, mapping: &mut TextureMapping, gesture: &mut Gesture) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Offset");
+        changed |= gesture.watch(&ui.add(egui::DragValue::new(&mut mapping.offset.x).speed(0.5)));
+        changed |= gesture.watch(&ui.add(egui::DragValue::new(&mut mapping.offset.y).speed(0.5)));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Scale");
+        changed |= gesture.watch(
+            &ui.add(
+                egui::DragValue::new(&mut mapping.scale.x)
+                    .speed(0.05)
+                    .clamp_range(0.05..=64.0),
+            ),
+        );
+        changed |= gesture.watch(
+            &ui.add(
+                egui::DragValue::new(&mut mapping.scale.y)
+                    .speed(0.05)
+                    .clamp_range(0.05..=64.0),
+            ),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Peg");
+        changed |= ui
+            .selectable_value(&mut mapping.peg, Peg::Bottom, "Bottom")
+            .changed();
+        changed |= ui
+            .selectable_value(&mut mapping.peg, Peg::Top, "Top")
+            .changed();
+    });
+    changed
+}
+
+/// Shows the texture with its thumbnail, clicking it opens a grid of every known texture to pick from
+fn texture_edit(
+    ui: &mut egui::Ui,
+    label: &str,
+    texture: &mut Option<TextureId>,
+    thumbnails: &[(TextureId, egui::TextureId)],
+) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let thumbnail = thumbnails
+            .iter()
+            .find(|(id, _)| Some(id) == texture.as_ref());
+        if let Some((_, thumbnail)) = thumbnail {
+            ui.image(*thumbnail, [THUMBNAIL_SIZE; 2]);
+        }
+        let name = texture
+            .as_ref()
+            .map_or_else(|| String::from("None"), |id| id.0.clone());
+        ui.menu_button(name, |ui| {
+            if ui.button("None").clicked() {
+                changed |= texture.take().is_some();
+                ui.close_menu();
+            }
+            egui::Grid::new("Textures").show(ui, |ui| {
+                for (index, (id, thumbnail)) in thumbnails.iter().enumerate() {
+                    let selected = texture.as_ref() == Some(id);
+                    let button = egui::ImageButton::new(*thumbnail, [THUMBNAIL_SIZE * 2.0; 2])
+                        .selected(selected);
+                    if ui.add(button).on_hover_text(&id.0).clicked() {
+                        changed |= !selected;
+                        *texture = Some(id.clone());
+                        ui.close_menu();
+                    }
+                    if index % PICKER_COLUMNS == PICKER_COLUMNS - 1 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    });
+    changed
+}
@@ -2,9 +2,27 @@ use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use bevy_prototype_debug_lines::*;
+use camera::{move_camera, FocusOn, FrameAll};
+use diagnostics::{diagnostics_ui, update_diagnostics, Diagnostics};
+use file::{file_ui, handle_close_requests, LevelFile};
+use grid::draw_grid;
+use history::{undo_keys, Edit, History};
+use inspector::inspector_ui;
 use itertools::Itertools;
+use pick::{pick, Pick};
 use portal_common::prelude::*;
+use select::{handle_select, SelectDrag, Selection};
 
+mod camera;
+mod diagnostics;
+mod file;
+mod grid;
+mod history;
+mod inspector;
+mod pick;
+mod select;
+
+/// Default spacing of the grid in world units
 const GRID_SIZE: f32 = 2.0;
 
 #[derive(Resource, Default)]
@@ -16,26 +34,91 @@ enum EditMode {
     Pan,
     Add,
     Remove,
+    Select,
 }
 
-#[derive(Resource, Default)]
+#[derive(Resource)]
 struct EditorState {
     mode: EditMode,
-    rounded_mouse: IVec2,
+    /// World position of the mouse, `None` while it is over the ui
+    cursor: Option<Vec2>,
+    /// Floor and roof height given to new sectors
     bottom_height: f32,
     top_height: f32,
-    points: Vec<IVec2>,
+    points: Vec<Vec2>,
+    /// What clicking in Remove mode would delete
+    hovered: Option<Pick>,
+    grid_size: f32,
+    snap_to_grid: bool,
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        Self {
+            mode: EditMode::default(),
+            cursor: None,
+            bottom_height: 0.0,
+            top_height: 40.0,
+            points: Vec::default(),
+            hovered: None,
+            grid_size: GRID_SIZE,
+            snap_to_grid: true,
+        }
+    }
+}
+
+impl EditorState {
+    /// Snaps a world position to the nearest grid point unless snapping is turned off
+    fn snap(&self, position: Vec2) -> Vec2 {
+        if self.snap_to_grid {
+            (position / self.grid_size).round() * self.grid_size
+        } else {
+            position
+        }
+    }
 }
 
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins)
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    // Closing is handled by handle_close_requests so unsaved work isn't lost
+                    close_when_requested: false,
+                    ..default()
+                })
+                .set(AssetPlugin {
+                    // Pick from the same textures the renderer draws with
+                    asset_folder: String::from("../portal_renderer/assets"),
+                    ..default()
+                }),
+        )
         .add_plugin(EguiPlugin)
         .add_plugin(DebugLinesPlugin::default())
+        .add_plugin(TexturePlugin)
         .insert_resource(EditorState::default())
         .insert_resource(EditingLevel::default())
+        .insert_resource(LevelFile::default())
+        .insert_resource(Selection::default())
+        .insert_resource(History::default())
+        .insert_resource(Diagnostics::default())
+        .add_event::<FrameAll>()
+        .add_event::<FocusOn>()
         .add_startup_system(setup)
-        .add_systems((editor_ui, handle_input, draw))
+        .add_systems((
+            file_ui.before(editor_ui),
+            editor_ui,
+            inspector_ui.after(editor_ui),
+            update_diagnostics.after(inspector_ui),
+            diagnostics_ui.after(update_diagnostics),
+            draw_grid.before(draw),
+            handle_input,
+            handle_select.after(handle_input),
+            move_camera,
+            handle_close_requests,
+            undo_keys.before(handle_input),
+            draw,
+        ))
         .run();
 }
 
@@ -50,31 +133,47 @@ fn setup(mut commands: Commands) {
 }
 
 fn handle_input(
+    mut contexts: EguiContexts,
     mut editor_state: ResMut<EditorState>,
     mut level: ResMut<EditingLevel>,
+    (mut history, mut selection): (ResMut<History>, ResMut<Selection>),
     windows: Query<&Window, With<PrimaryWindow>>,
     camera_q: Query<(&Camera, &GlobalTransform)>,
     buttons: Res<Input<MouseButton>>,
 ) {
+    editor_state.hovered = None;
+    editor_state.cursor = None;
+
+    // Clicks on the side panel are meant for egui
+    if contexts.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+
     if let Ok((camera, global_transform)) = camera_q.get_single() {
         let window = windows.single();
         if let Some(world_position) = window
             .cursor_position()
             .and_then(|cursor| camera.viewport_to_world_2d(global_transform, cursor))
         {
-            let rounded_pos = world_position.round().as_ivec2();
+            editor_state.cursor = Some(world_position);
+            let snapped_pos = editor_state.snap(world_position);
 
             match editor_state.mode {
+                // Dragging the view is done by move_camera
                 EditMode::Pan => {}
                 EditMode::Add => {
                     let lean = 10.0;
+                    // Only clicks change anything, so only they need the state to undo to
+                    let before = (buttons.just_pressed(MouseButton::Left)
+                        || buttons.just_pressed(MouseButton::Right))
+                    .then(|| (editor_state.points.clone(), level.0.clone()));
                     if buttons.just_pressed(MouseButton::Right) {
                         let copied_array = editor_state.points.clone();
                         for (idx, point) in copied_array.iter().enumerate() {
-                            if rounded_pos.x as f32 >= point.x as f32 - lean
-                                && rounded_pos.x as f32 <= point.x as f32 + lean
-                                && rounded_pos.y as f32 >= point.y as f32 - lean
-                                && rounded_pos.y as f32 <= point.y as f32 + lean
+                            if snapped_pos.x >= point.x - lean
+                                && snapped_pos.x <= point.x + lean
+                                && snapped_pos.y >= point.y - lean
+                                && snapped_pos.y <= point.y + lean
                                 && idx == copied_array.len() - 1
                             {
                                 editor_state.points.remove(idx);
@@ -82,72 +181,197 @@ fn handle_input(
                         }
                     }
                     if buttons.just_pressed(MouseButton::Left) {
-                        let len = editor_state.points.len();
-                        if editor_state.points.is_empty() {
-                            editor_state.points.push(rounded_pos);
-                        } else if len == 1 {
-                            if rounded_pos.x <= editor_state.points[0].x {
-                                editor_state.points.clear();
-                                return;
-                            }
-                            editor_state.points.push(rounded_pos);
+                        // Points can go either way round, add_sector turns the walls to face inwards
+                        if editor_state.points.len() < 2 {
+                            editor_state.points.push(snapped_pos);
                         } else {
                             // if let Some(last) = editor_state.points.last() {
                             let point = editor_state.points[0];
-                            if rounded_pos.x as f32 >= point.x as f32 - lean
-                                && rounded_pos.x as f32 <= point.x as f32 + lean
-                                && rounded_pos.y as f32 >= point.y as f32 - lean
-                                && rounded_pos.y as f32 <= point.y as f32 + lean
+                            if snapped_pos.x >= point.x - lean
+                                && snapped_pos.x <= point.x + lean
+                                && snapped_pos.y >= point.y - lean
+                                && snapped_pos.y <= point.y + lean
                             {
                                 editor_state.points.push(point);
-                                let mut sector = Sector::new(0.0, 40.0);
+                                let mut sector = Sector::new(
+                                    editor_state.bottom_height,
+                                    editor_state.top_height,
+                                );
                                 editor_state
                                     .points
                                     .iter()
                                     .tuple_windows()
                                     .for_each(|(a, b)| {
-                                        let wall = Wall {
-                                            points: [a.as_vec2(), b.as_vec2()],
-                                            color: PixColor(255, 255, 255, 255),
-                                            uv: Vec2::ONE,
-                                        };
-                                        sector.walls.push(wall);
+                                        // White and untextured until given a look in the inspector
+                                        sector.add_wall(*a, *b, WallTexture::default().color);
                                     });
-                                level.0.sectors.push(sector);
+                                let id = level.0.add_sector(sector);
+                                // Textures run on around the corners from the first wall
+                                if let Some(sector) = level.0.sector_mut(id) {
+                                    sector.align_textures(0);
+                                }
+                                // Walls drawn along a neighbour open into it
+                                level.0.link_portals();
+                                // Splitting walls may have shifted indices
+                                selection.items.clear();
                                 editor_state.points.clear();
                             } else {
-                                editor_state.points.push(rounded_pos);
+                                editor_state.points.push(snapped_pos);
                             }
                             // }
                         }
                     }
+
+                    if let Some((points_before, level_before)) = before {
+                        let label = if level.0.sectors.len() != level_before.sectors.len() {
+                            "Add sector"
+                        } else if editor_state.points.len() > points_before.len() {
+                            "Place point"
+                        } else {
+                            "Remove point"
+                        };
+                        history.push(
+                            Edit::new(label)
+                                .sectors(&level_before, &level.0)
+                                .points(points_before, editor_state.points.clone()),
+                        );
+                    }
                 }
-                EditMode::Remove => {}
+                EditMode::Remove => {
+                    editor_state.hovered = pick(&level.0, world_position);
+                    if buttons.just_pressed(MouseButton::Left) {
+                        let before = level.0.clone();
+                        let label = match editor_state.hovered {
+                            Some(Pick::Wall(id, index)) => {
+                                // Closes the outline again, or drops a sector left too small
+                                level.0.remove_wall(id, index);
+                                "Remove wall"
+                            }
+                            Some(Pick::Sector(id)) => {
+                                level.0.remove_sector(id);
+                                "Remove sector"
+                            }
+                            Some(Pick::Vertex(_)) | None => return,
+                        };
+                        history.push(Edit::new(label).sectors(&before, &level.0));
+                        editor_state.hovered = None;
+                        // Indices of what is left may have shifted
+                        selection.items.clear();
+                    }
+                }
+                // Picking and dragging is done by handle_select
+                EditMode::Select => {}
             }
         }
     }
 }
 
-fn editor_ui(mut contexts: EguiContexts, mut editor_state: ResMut<EditorState>) {
+fn editor_ui(
+    mut contexts: EguiContexts,
+    mut editor_state: ResMut<EditorState>,
+    mut frame_events: EventWriter<FrameAll>,
+    (mut history, mut level, mut selection): (
+        ResMut<History>,
+        ResMut<EditingLevel>,
+        ResMut<Selection>,
+    ),
+) {
     egui::SidePanel::left("Editor").show(contexts.ctx_mut(), |ui| {
         ui.label("Editor Mode");
         ui.radio_value(&mut editor_state.mode, EditMode::Pan, "Pan");
         ui.radio_value(&mut editor_state.mode, EditMode::Add, "Add");
         ui.radio_value(&mut editor_state.mode, EditMode::Remove, "Remove");
+        ui.radio_value(&mut editor_state.mode, EditMode::Select, "Select");
+        ui.separator();
+        ui.label("New sectors");
+        ui.add(
+            egui::DragValue::new(&mut editor_state.bottom_height)
+                .speed(0.5)
+                .prefix("Floor "),
+        );
+        ui.add(
+            egui::DragValue::new(&mut editor_state.top_height)
+                .speed(0.5)
+                .prefix("Roof "),
+        );
+        ui.separator();
+        ui.label("Grid");
+        ui.add(
+            egui::DragValue::new(&mut editor_state.grid_size)
+                .clamp_range(0.25..=64.0)
+                .speed(0.25)
+                .prefix("Spacing "),
+        );
+        ui.checkbox(&mut editor_state.snap_to_grid, "Snap to grid");
+        ui.separator();
+        if ui
+            .button("Frame all")
+            .on_hover_text("Fit the whole level on screen (F)")
+            .clicked()
+        {
+            frame_events.send(FrameAll);
+        }
+        if ui
+            .button("Link portals")
+            .on_hover_text("Turn every wall shared by two sectors into a portal")
+            .clicked()
+        {
+            let before = level.0.clone();
+            level.0.link_portals();
+            selection.items.clear();
+            history.push(Edit::new("Link portals").sectors(&before, &level.0));
+        }
+        ui.separator();
+        ui.label("History");
+        history.ui(ui, &mut level, &mut editor_state.points, &mut selection);
     });
 }
 
 fn draw(
     editor_state: Res<EditorState>,
+    selection: Res<Selection>,
     mut lines: ResMut<DebugLines>,
     level: ResMut<EditingLevel>,
 ) {
     for point in editor_state.points.iter() {
-        draw_point(&mut lines, point.clone().as_vec2());
+        draw_point(&mut lines, *point);
     }
     for sector in level.0.sectors.iter() {
-        for wall in sector.walls.iter() {
-            lines.line(wall.points[0].extend(0.0), wall.points[1].extend(0.0), 0.0);
+        let sector_hovered = editor_state.hovered == Some(Pick::Sector(sector.id));
+        let sector_selected = selection.contains(Pick::Sector(sector.id));
+        for (index, wall) in sector.walls.iter().enumerate() {
+            // Show what is about to be removed
+            let color =
+                if sector_hovered || editor_state.hovered == Some(Pick::Wall(sector.id, index)) {
+                    Color::RED
+                } else if sector_selected || selection.contains(Pick::Wall(sector.id, index)) {
+                    Color::YELLOW
+                } else {
+                    Color::WHITE
+                };
+            lines.line_colored(
+                wall.points[0].extend(0.0),
+                wall.points[1].extend(0.0),
+                0.0,
+                color,
+            );
+        }
+    }
+
+    for item in selection.items.iter() {
+        if let Pick::Vertex(point) = item {
+            draw_point(&mut lines, *point);
+        }
+    }
+    if let SelectDrag::Box { start, end } = selection.drag {
+        let corners = [
+            start,
+            Vec2::new(end.x, start.y),
+            end,
+            Vec2::new(start.x, end.y),
+        ];
+        for (a, b) in corners.iter().circular_tuple_windows() {
+            lines.line_colored(a.extend(0.0), b.extend(0.0), 0.0, Color::YELLOW);
         }
     }
 }
@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+use portal_common::prelude::*;
+
+/// How close in world units the cursor has to be to grab a wall
+pub const PICK_DISTANCE: f32 = 4.0;
+
+/// Something in the level under the cursor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pick {
+    /// Every wall end at this position
+    Vertex(Vec2),
+    Wall(SectorId, usize),
+    Sector(SectorId),
+}
+
+impl Pick {
+    /// Wall ends that move along when this gets dragged
+    pub fn points(self, level: &Level) -> Vec<Vec2> {
+        match self {
+            Pick::Vertex(point) => vec![point],
+            Pick::Wall(id, index) => level
+                .sector(id)
+                .and_then(|sector| sector.walls.get(index))
+                .map(|wall| wall.points.to_vec())
+                .unwrap_or_default(),
+            Pick::Sector(id) => level
+                .sector(id)
+                .map(|sector| sector.walls.iter().flat_map(|wall| wall.points).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Finds the closest wall end to the cursor
+pub fn pick_vertex(level: &Level, position: Vec2) -> Option<Vec2> {
+    level
+        .sectors
+        .iter()
+        .flat_map(|sector| sector.walls.iter())
+        .flat_map(|wall| wall.points)
+        .map(|point| (point, point.distance(position)))
+        .filter(|(_, distance)| *distance <= PICK_DISTANCE)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(point, _)| point)
+}
+
+/// Finds the wall or sector under the cursor, walls close enough win over the sector they belong to
+pub fn pick(level: &Level, position: Vec2) -> Option<Pick> {
+    let wall =
+        level
+            .sectors
+            .iter()
+            .flat_map(|sector| {
+                let inside = sector.contains(position);
+                sector.walls.iter().enumerate().map(move |(index, wall)| {
+                    (sector.id, index, wall.distance_to(position), inside)
+                })
+            })
+            .filter(|(_, _, distance, _)| *distance <= PICK_DISTANCE)
+            // Both sides of a portal are equally close, take the one on the cursors side
+            .min_by(|a, b| a.2.total_cmp(&b.2).then(b.3.cmp(&a.3)));
+
+    if let Some((id, index, _, _)) = wall {
+        return Some(Pick::Wall(id, index));
+    }
+
+    level
+        .sectors
+        .iter()
+        .find(|sector| sector.contains(position))
+        .map(|sector| Pick::Sector(sector.id))
+}
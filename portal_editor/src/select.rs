@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+use portal_common::prelude::*;
+
+use crate::{
+    history::{Edit, History},
+    pick::{pick, pick_vertex, Pick},
+    EditMode, EditingLevel, EditorState,
+};
+
+/// What the left mouse button is doing in Select mode
+#[derive(Default, Clone, Copy)]
+pub enum SelectDrag {
+    #[default]
+    None,
+    /// Moving the selection. The `anchor` vertex follows the cursor onto the grid and everything
+    /// else selected keeps its offset to it. `grab` is where the button went down.
+    Move {
+        grab: Vec2,
+        anchor: Vec2,
+        /// From the cursor to the anchor, zero when a vertex was grabbed
+        offset: Vec2,
+    },
+    /// Selecting everything inside the box between the two corners
+    Box { start: Vec2, end: Vec2 },
+}
+
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub items: Vec<Pick>,
+    pub drag: SelectDrag,
+    /// The level as it was when the current move started, so the whole drag undoes at once
+    moved_from: Option<Level>,
+}
+
+impl Selection {
+    pub fn contains(&self, item: Pick) -> bool {
+        self.items.contains(&item)
+    }
+
+    /// The selected sector, or the one the first selected wall belongs to
+    pub fn sector(&self) -> Option<SectorId> {
+        let wall_sector = self.items.iter().find_map(|item| match item {
+            Pick::Wall(id, _) => Some(*id),
+            _ => None,
+        });
+        self.items
+            .iter()
+            .find_map(|item| match item {
+                Pick::Sector(id) => Some(*id),
+                _ => None,
+            })
+            .or(wall_sector)
+    }
+
+    fn add(&mut self, item: Pick) {
+        if !self.contains(item) {
+            self.items.push(item);
+        }
+    }
+
+    fn toggle(&mut self, item: Pick) {
+        if self.contains(item) {
+            self.items.retain(|selected| *selected != item);
+        } else {
+            self.items.push(item);
+        }
+    }
+
+    /// Every wall end that moves when the selection is dragged
+    fn points(&self, level: &Level) -> Vec<Vec2> {
+        let mut points: Vec<Vec2> = Vec::new();
+        for point in self.items.iter().flat_map(|item| item.points(level)) {
+            if !points.contains(&point) {
+                points.push(point);
+            }
+        }
+        points
+    }
+}
+
+pub fn handle_select(
+    editor_state: Res<EditorState>,
+    mut selection: ResMut<Selection>,
+    mut level: ResMut<EditingLevel>,
+    mut history: ResMut<History>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if editor_state.mode != EditMode::Select {
+        if let Some(before) = selection.moved_from.take() {
+            history.push(Edit::new("Move").sectors(&before, &level.0));
+        }
+        selection.drag = SelectDrag::None;
+        return;
+    }
+    // Handled before looking at the cursor, which is gone when the button comes up over a panel
+    if buttons.just_released(MouseButton::Left) {
+        if let SelectDrag::Box { start, end } = selection.drag {
+            select_box(&mut selection, &level.0, start.min(end), start.max(end));
+        }
+        if let Some(before) = selection.moved_from.take() {
+            history.push(Edit::new("Move").sectors(&before, &level.0));
+        }
+        selection.drag = SelectDrag::None;
+        return;
+    }
+    let Some(cursor) = editor_state.cursor else {
+        return;
+    };
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+
+    if buttons.just_pressed(MouseButton::Left) {
+        // Vertices are the smallest target so they get first pick
+        let picked = pick_vertex(&level.0, cursor)
+            .map(Pick::Vertex)
+            .or_else(|| pick(&level.0, cursor));
+        match picked {
+            Some(item) => {
+                if shift {
+                    selection.toggle(item);
+                } else if !selection.contains(item) {
+                    selection.items = vec![item];
+                }
+                // A grabbed wall or sector is moved by its vertex closest to the cursor
+                let anchor = match item {
+                    Pick::Vertex(point) => point,
+                    _ => selection
+                        .points(&level.0)
+                        .into_iter()
+                        .min_by(|a, b| {
+                            a.distance_squared(cursor)
+                                .total_cmp(&b.distance_squared(cursor))
+                        })
+                        .unwrap_or(cursor),
+                };
+                let offset = match item {
+                    Pick::Vertex(_) => Vec2::ZERO,
+                    _ => anchor - cursor,
+                };
+                selection.drag = SelectDrag::Move {
+                    grab: cursor,
+                    anchor,
+                    offset,
+                };
+                selection.moved_from = Some(level.0.clone());
+            }
+            None => {
+                if !shift {
+                    selection.items.clear();
+                }
+                selection.drag = SelectDrag::Box {
+                    start: cursor,
+                    end: cursor,
+                };
+            }
+        }
+    }
+
+    match selection.drag {
+        SelectDrag::Move {
+            grab,
+            anchor,
+            offset,
+        } => {
+            // Nothing moves before the cursor does, so clicking to select leaves vertices alone
+            let target = editor_state.snap(cursor + offset);
+            let delta = target - anchor;
+            if cursor != grab && delta != Vec2::ZERO {
+                let points = selection.points(&level.0);
+                move_points(&mut level.0, &points, delta);
+                for item in selection.items.iter_mut() {
+                    if let Pick::Vertex(point) = item {
+                        *point += delta;
+                    }
+                }
+                selection.drag = SelectDrag::Move {
+                    grab,
+                    anchor: target,
+                    offset,
+                };
+            }
+        }
+        SelectDrag::Box { start, .. } => {
+            selection.drag = SelectDrag::Box { start, end: cursor };
+        }
+        SelectDrag::None => {}
+    }
+}
+
+/// Moves every wall end sitting on one of `points`, so walls sharing a vertex stay joined
+fn move_points(level: &mut Level, points: &[Vec2], delta: Vec2) {
+    for point in level
+        .sectors
+        .iter_mut()
+        .flat_map(|sector| sector.walls.iter_mut())
+        .flat_map(|wall| wall.points.iter_mut())
+    {
+        if points.contains(point) {
+            *point += delta;
+        }
+    }
+}
+
+/// Adds the vertices inside the box and the walls and sectors that lie completely inside it
+fn select_box(selection: &mut Selection, level: &Level, min: Vec2, max: Vec2) {
+    let inside = |point: Vec2| point.cmpge(min).all() && point.cmple(max).all();
+    for sector in level.sectors.iter() {
+        for (index, wall) in sector.walls.iter().enumerate() {
+            for point in wall.points {
+                if inside(point) {
+                    selection.add(Pick::Vertex(point));
+                }
+            }
+            if wall.points.into_iter().all(inside) {
+                selection.add(Pick::Wall(sector.id, index));
+            }
+        }
+        if !sector.walls.is_empty() && sector.walls.iter().all(|wall| inside(wall.points[0])) {
+            selection.add(Pick::Sector(sector.id));
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+use portal_common::{geometry::closest_point_on_segment, prelude::*};
+
+/// How many times a move gets pushed back out of walls, enough to settle into corners
+const PUSH_ITERATIONS: usize = 4;
+
+/// Size of the body that bumps into walls
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Collider {
+    /// Closest the middle of the body gets to a wall
+    pub radius: f32,
+    /// How far above the feet the view sits, also the height needed to fit through a portal
+    pub eye_height: f32,
+    /// Highest floor step that can be walked up onto
+    pub step_height: f32,
+}
+
+impl Default for Collider {
+    fn default() -> Self {
+        Self {
+            radius: 2.5,
+            eye_height: 12.0,
+            step_height: 6.0,
+        }
+    }
+}
+
+impl Collider {
+    /// Whether the body with its feet at `feet` fits into `sector` from a neighbour
+    pub fn can_enter(&self, sector: &Sector, feet: f32) -> bool {
+        sector.floor <= feet + self.step_height
+            && sector.roof - feet.max(sector.floor) >= self.eye_height
+    }
+}
+
+/// Moves from `from` to `to` on the map, sliding along every wall that is in the way.
+///
+/// Walls of the sector we are in and the sectors next to it are checked. Portal walls only
+/// block when the sector behind them is too high to step into or too low to fit in.
+pub fn slide(
+    level: &Level,
+    current: SectorId,
+    (from, to): (Vec2, Vec2),
+    feet: f32,
+    collider: &Collider,
+) -> Vec2 {
+    let Some(sector) = level.sector(current) else {
+        return to;
+    };
+    let blocking: Vec<[Vec2; 2]> = std::iter::once(sector)
+        .chain(sector.neighbours().filter_map(|id| level.sector(id)))
+        .flat_map(|sector| sector.walls.iter())
+        .filter(|wall| match wall.portal.and_then(|id| level.sector(id)) {
+            Some(behind) => !collider.can_enter(behind, feet),
+            None => true,
+        })
+        .map(|wall| wall.points)
+        .collect();
+
+    // Short steps so a fast move can't skip over a wall
+    let max_step = collider.radius / 2.0;
+    let steps = ((to - from).length() / max_step).ceil().max(1.0);
+    let step = (to - from) / steps;
+    let mut position = from;
+    for _ in 0..steps as usize {
+        let came_from = position;
+        position += step;
+        for _ in 0..PUSH_ITERATIONS {
+            let mut pushed = false;
+            for [start, end] in blocking.iter() {
+                let closest = closest_point_on_segment(position, *start, *end);
+                let offset = position - closest;
+                let distance = offset.length();
+                if distance >= collider.radius {
+                    continue;
+                }
+                let away = if distance > 0.0 {
+                    offset / distance
+                } else {
+                    // Landed right on the wall, back out to the side the step came from
+                    let normal = (*end - *start).perp().normalize_or_zero();
+                    let back = came_from - closest;
+                    match back.dot(normal) {
+                        side if side > 0.0 => normal,
+                        side if side < 0.0 => -normal,
+                        _ => back.normalize_or_zero(),
+                    }
+                };
+                // Only the part of the move into the wall is taken away, the rest slides along
+                position = closest + away * collider.radius;
+                pushed = true;
+            }
+            if !pushed {
+                break;
+            }
+        }
+    }
+    position
+}
@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+use portal_common::prelude::*;
+
+/// Size of the cells the level gets bucketed into for looking up sectors
+const GRID_CELL_SIZE: f32 = 16.0;
+
+/// The sector an entity stands in, `None` while it is outside of every sector.
+/// Only written when it changes, so `Changed<CurrentSector>` means a sector was entered.
+#[derive(Component, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CurrentSector(pub Option<SectorId>);
+
+/// Builds the lookup grid next to the level whenever the level is loaded or reloaded
+pub fn update_sector_grid(mut commands: Commands, levels: Query<(Entity, &Level), Changed<Level>>) {
+    for (entity, level) in levels.iter() {
+        commands
+            .entity(entity)
+            .insert(SectorGrid::new(level, GRID_CELL_SIZE));
+    }
+}
+
+pub fn track_current_sector(
+    mut trackers: Query<(&Transform, &mut CurrentSector)>,
+    levels: Query<(&Level, Option<&SectorGrid>)>,
+) {
+    let Some((level, grid)) = levels.iter().next() else {
+        return;
+    };
+    for (transform, mut current) in trackers.iter_mut() {
+        let position = Vec2::new(transform.translation.x, transform.translation.z);
+        let sector = find_sector(level, grid, current.0, position);
+        if current.0 != sector {
+            current.0 = sector;
+        }
+    }
+}
+
+/// Looks in the last sector and the ones it opens into first, as moving
+/// rarely gets further than that in one frame
+pub fn find_sector(
+    level: &Level,
+    grid: Option<&SectorGrid>,
+    last: Option<SectorId>,
+    position: Vec2,
+) -> Option<SectorId> {
+    if let Some(last) = last.and_then(|id| level.sector(id)) {
+        if last.contains(position) {
+            return Some(last.id);
+        }
+        let neighbour = last
+            .neighbours()
+            .filter_map(|id| level.sector(id))
+            .find(|neighbour| neighbour.contains(position));
+        if let Some(neighbour) = neighbour {
+            return Some(neighbour.id);
+        }
+    }
+    match grid {
+        Some(grid) => grid.sector_at(level, position),
+        None => level.sector_at(position),
+    }
+}
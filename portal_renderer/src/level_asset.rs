@@ -0,0 +1,84 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use portal_common::prelude::*;
+
+/// A `.level` file from the assets folder, see `portal_common::serialize` for the format
+#[derive(TypeUuid, Deref)]
+#[uuid = "8ebad4d6-143a-42b1-8883-24081b8cd592"]
+pub struct LevelAsset(pub Level);
+
+/// The level file being shown, its [`Level`] gets swapped in whenever the file changes
+#[derive(Resource, Deref)]
+pub struct LevelHandle(pub Handle<LevelAsset>);
+
+#[derive(Default)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level = Level::from_ron(std::str::from_utf8(bytes)?)?;
+            load_context.set_default_asset(LoadedAsset::new(LevelAsset(level)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+pub struct LevelAssetPlugin;
+
+impl Plugin for LevelAssetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<LevelAsset>()
+            .init_asset_loader::<LevelLoader>()
+            .add_system(update_level);
+    }
+}
+
+fn update_level(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<LevelAsset>>,
+    levels: Res<Assets<LevelAsset>>,
+    level_handle: Option<Res<LevelHandle>>,
+    level_query: Query<Entity, With<Level>>,
+) {
+    let Some(level_handle) = level_handle else {
+        return;
+    };
+
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        if handle != &**level_handle {
+            continue;
+        }
+
+        if let Some(level) = levels.get(handle) {
+            // Still shown, but broken geometry usually means broken drawing
+            for diagnostic in level.validate() {
+                warn!("{diagnostic}");
+            }
+            // The asset keeps its own copy so the next reload starts from the file again
+            match level_query.get_single() {
+                Ok(entity) => {
+                    commands.entity(entity).insert(level.0.clone());
+                }
+                Err(_) => {
+                    commands.spawn(level.0.clone());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use portal_common::prelude::*;
+
+/// Depth at which a fully lit surface has faded to black
+const FALLOFF_DISTANCE: f32 = 250.0;
+
+/// How bright something lit by `light` looks at `depth` in front of the viewpoint, from 0 to 1
+pub fn brightness(light: f32, depth: f32) -> f32 {
+    (light - depth / FALLOFF_DISTANCE).clamp(0.0, 1.0)
+}
+
+/// Darkens a color, keeping its alpha
+pub fn shade(PixColor(r, g, b, a): PixColor, brightness: f32) -> PixColor {
+    let scale = (brightness * 256.0) as u32;
+    let channel = |value: u8| ((value as u32 * scale) >> 8) as u8;
+    PixColor(channel(r), channel(g), channel(b), a)
+}
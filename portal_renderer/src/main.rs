@@ -1,15 +1,33 @@
-use std::{f32::consts::PI, mem::swap};
+use std::{collections::VecDeque, f32::consts::PI, ops::Range};
 
 use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_pixel_buffer::prelude::*;
+use collision::{slide, Collider};
+use current_sector::{find_sector, track_current_sector, update_sector_grid, CurrentSector};
+use level_asset::{LevelAssetPlugin, LevelHandle};
+use light::{brightness, shade};
 use portal_common::prelude::*;
+use texture_cache::{update_texture_cache, Texture, TextureCache};
+use walk::Walk;
 
-#[derive(Resource, Deref, DerefMut)]
-struct WallImage(pub Handle<Image>);
+mod collision;
+mod current_sector;
+mod level_asset;
+mod light;
+mod texel;
+mod texture_cache;
+mod walk;
 
 #[derive(Component)]
 struct Viewpoint;
 
+/// Field of view scale used when projecting onto the screen
+const FOV: f32 = 90.0;
+/// Depth in front of the viewpoint that walls get clipped at
+const NEAR: f32 = 0.05;
+/// How many portals deep the view is followed, stops broken portal loops from hanging the renderer
+const MAX_PORTAL_DEPTH: u8 = 32;
+
 #[derive(SystemParam)]
 struct PixelHandler<'w, 's> {
     // commands: Commands<'w, 's>,
@@ -43,8 +61,15 @@ impl<'w, 's> PixelHandler<'w, 's> {
 
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins)
+        .add_plugins(DefaultPlugins.set(AssetPlugin {
+            // Reload levels and textures as soon as they are saved
+            watch_for_changes: true,
+            ..default()
+        }))
         .add_plugin(PixelBufferPlugin)
+        .add_plugin(LevelAssetPlugin)
+        .add_plugin(TexturePlugin)
+        .init_resource::<TextureCache>()
         .add_startup_system(
             PixelBufferBuilder::new()
                 .with_size(PixelBufferSize::pixel_size(UVec2::new(4, 4)))
@@ -53,6 +78,9 @@ fn main() {
         )
         .add_startup_system(setup)
         .add_system(move_player)
+        .add_system(update_sector_grid.before(track_current_sector))
+        .add_system(track_current_sector.after(move_player).before(draw))
+        .add_system(update_texture_cache.before(draw))
         .add_system(clear.before(draw))
         .add_system(draw)
         .run();
@@ -63,62 +91,27 @@ fn clear(mut pixel_handler: PixelHandler) {
 }
 
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.insert_resource(WallImage(asset_server.load("Bricks_01-128x128.png")));
-    commands.spawn((Transform::from_xyz(70.0, 20.0, -110.0), Viewpoint));
-    let mut level = Level::default();
-    let mut sector = Sector::new(0.0, 10.0);
-    sector.add_wall(
-        Vec2::new(0.0, 25.0),
-        Vec2::new(0.0, 0.0),
-        PixColor(128, 128, 128, 255),
-    );
-    sector.add_wall(
-        Vec2::new(25.0, 25.0),
-        Vec2::new(0.0, 25.0),
-        PixColor(100, 100, 100, 255),
-    );
-    sector.add_wall(
-        Vec2::new(25.0, 0.0),
-        Vec2::new(25.0, 25.0),
-        PixColor(128, 128, 128, 255),
-    );
-    sector.add_wall(
-        Vec2::new(0.0, 0.0),
-        Vec2::new(25.0, 0.0),
-        PixColor(100, 100, 100, 255),
-    );
-    level.sectors.push(sector);
-    let mut sector = Sector::new(10.0, 40.0);
-    sector.add_wall(
-        Vec2::new(30.0, 150.0),
-        Vec2::new(30.0, 30.0),
-        PixColor(200, 0, 0, 255),
-    );
-    sector.add_wall(
-        Vec2::new(50.0, 150.0),
-        Vec2::new(30.0, 150.0),
-        PixColor(255, 0, 0, 255),
-    );
-    sector.add_wall(
-        Vec2::new(50.0, 30.0),
-        Vec2::new(50.0, 150.0),
-        PixColor(200, 0, 0, 255),
-    );
-    sector.add_wall(
-        Vec2::new(30.0, 30.0),
-        Vec2::new(50.0, 30.0),
-        PixColor(255, 0, 0, 255),
-    );
-    level.sectors.push(sector);
-    commands.spawn(level);
+    commands.spawn((
+        Transform::from_xyz(20.0, 15.0, 5.0),
+        Viewpoint,
+        CurrentSector::default(),
+        Collider::default(),
+        Walk::default(),
+    ));
+    commands.insert_resource(LevelHandle(asset_server.load("start.level")));
 }
 
 fn move_player(
-    mut player_query: Query<&mut Transform, With<Viewpoint>>,
+    mut player_query: Query<
+        (&mut Transform, &CurrentSector, &Collider, &mut Walk),
+        With<Viewpoint>,
+    >,
+    level_query: Query<&Level>,
     keys: Res<Input<KeyCode>>,
     time: Res<Time>,
 ) {
-    if let Ok(mut transform) = player_query.get_single_mut() {
+    if let Ok((mut transform, current, collider, mut walk)) = player_query.get_single_mut() {
+        let start = Vec2::new(transform.translation.x, transform.translation.z);
         let (angle_up, angle, z_angle) = transform.rotation.to_euler(EulerRot::XYZ);
         // Bevy returns half for some reason
         let angle = angle * 2.0;
@@ -145,12 +138,44 @@ fn move_player(
             transform.translation.x -= dz;
             transform.translation.z += dx;
         }
-        if keys.pressed(KeyCode::Space) {
-            transform.translation.y += 4.0 * dt * speed;
+
+        if keys.just_pressed(KeyCode::N) {
+            walk.flying = !walk.flying;
+            walk.vertical_speed = 0.0;
         }
 
-        if keys.pressed(KeyCode::C) {
-            transform.translation.y -= 4.0 * dt * speed;
+        // Keep out of walls and on the floor, outside of every sector there is nothing to stand on
+        match (level_query.iter().next(), current.0) {
+            (Some(level), Some(sector)) if !walk.flying => {
+                let target = Vec2::new(transform.translation.x, transform.translation.z);
+                let feet = transform.translation.y - collider.eye_height;
+                let position = slide(level, sector, (start, target), feet, collider);
+                transform.translation.x = position.x;
+                transform.translation.z = position.y;
+
+                // The sector we moved into decides the floor, current is only updated after us
+                let standing_in = find_sector(level, None, Some(sector), position)
+                    .and_then(|id| level.sector(id));
+                if let Some(standing_in) = standing_in {
+                    let jump = keys.just_pressed(KeyCode::Space);
+                    walk.update(
+                        &mut transform.translation.y,
+                        standing_in,
+                        collider,
+                        dt,
+                        jump,
+                    );
+                }
+            }
+            _ => {
+                if keys.pressed(KeyCode::Space) {
+                    transform.translation.y += 4.0 * dt * speed;
+                }
+
+                if keys.pressed(KeyCode::C) {
+                    transform.translation.y -= 4.0 * dt * speed;
+                }
+            }
         }
         let mut local_angle = angle;
         let mut local_angle_up = angle_up;
@@ -186,325 +211,436 @@ fn move_player(
     }
 }
 
-fn draw(
-    mut pixel_handler: PixelHandler,
-    player_query: Query<&Transform, With<Viewpoint>>,
-    // mut sectors: ResMut<Sectors>,
-    wall_handle: Res<WallImage>,
-    mut level_query: Query<&mut Level>,
-) {
-    if let Ok(transform) = player_query.get_single() {
-        // if let Some(image) = pixel_handler
-        //     .pixel_wrapper
-        //     .images()
-        //     .get(&*wall_handle)
-        //     .cloned()
-        // {
-        //     test_textures(&image, &mut pixel_handler);
-        // }
+/// Camera values needed to bring world positions onto the screen
+struct View {
+    position: Vec3,
+    cos: f32,
+    sin: f32,
+    // Vertical shear used to look up and down
+    look: f32,
+    center: Vec2,
+}
+
+impl View {
+    fn new(transform: &Transform, size: UVec2) -> Self {
         let (angle_up, angle, _) = transform.rotation.to_euler(EulerRot::XYZ);
         let angle = angle * 2.0;
         let angle_up = angle_up * 2.0;
-        let player_cos = angle.cos();
-        let player_sin = angle.sin();
-        if let Some(mut level) = level_query.iter_mut().next() {
-            // Sort the levels sectors from back to front
-            bubble_sort(&mut level.sectors);
-            level.sectors.reverse();
-            for sector in level.sectors.iter_mut() {
-                // Reset sector depth
-                sector.depth = 0.0;
-
-                // Set what surface we are rendering based off of player location relative to this sector
-                let cycles = if transform.translation.y < sector.floor {
-                    sector.surface = Surface::Bottom;
-                    sector.x_points = vec![pixel_handler.height(); pixel_handler.width() as usize];
-                    2
-                } else if transform.translation.y > sector.roof {
-                    sector.surface = Surface::Top;
-                    sector.x_points = vec![0; pixel_handler.width() as usize];
-                    2
-                } else {
-                    sector.surface = Surface::Normal;
-                    1
-                };
-
-                // Two loops are needed for filling in top and bottoms
-                for i in 0..cycles {
-                    // Loop through the sector walls
-                    for wall in sector.walls.iter() {
-                        // Temporary local_wall varibale
-                        let mut local_wall = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0)];
-
-                        // Offset bottom two points of wall by player position
-                        /*
-                            In 3d graphics its more we move the world instead of the camera.
-                            So first we must move all the world using the camera pos
-                        */
-                        let mut x1 = wall.points[0].x - transform.translation.x;
-                        let mut z1 = wall.points[0].y - transform.translation.z;
-                        let mut x2 = wall.points[1].x - transform.translation.x;
-                        let mut z2 = wall.points[1].y - transform.translation.z;
-
-                        // When we are on back edge of wall flip points
-                        if i == 1 {
-                            swap(&mut x1, &mut x2);
-                            swap(&mut z1, &mut z2);
-                        }
-
-                        // Use player rotation to adjust the points of the wall
-                        local_wall[0].x = x1 * player_cos - z1 * player_sin;
-                        local_wall[1].x = x2 * player_cos - z2 * player_sin;
-
-                        local_wall[0].z = z1 * player_cos + x1 * player_sin;
-                        local_wall[1].z = z2 * player_cos + x2 * player_sin;
-
-                        // Translate the height based off of the sector, player location, and angle
-                        local_wall[0].y = sector.floor - transform.translation.y
-                            + (angle_up.to_degrees() * local_wall[0].z / 32.0);
-                        local_wall[1].y = sector.floor - transform.translation.y
-                            + (angle_up.to_degrees() * local_wall[1].z / 32.0);
-
-                        let top_y1 = sector.roof - transform.translation.y
-                            + (angle_up.to_degrees() * local_wall[0].z / 32.0);
-                        let top_y2 = sector.roof - transform.translation.y
-                            + (angle_up.to_degrees() * local_wall[1].z / 32.0);
-
-                        // Add this walls depth to the sector
-                        sector.depth += Vec2::ZERO.distance(Vec2::new(
-                            (local_wall[0].x + local_wall[0].z) / 2.0,
-                            (local_wall[1].x + local_wall[1].z) / 2.0,
-                        ));
-
-                        // If the local wall is behind the player we don't draw it
-                        if local_wall[0].z < 0.0 && local_wall[1].z < 0.0 {
-                            continue;
-                        }
-
-                        // New points to use for clipping bottom 1 and 2 and top 1 and 2
-                        let mut b1 = Vec3::new(local_wall[0].x, local_wall[0].y, local_wall[0].z);
-                        let mut b2 = Vec3::new(local_wall[1].x, local_wall[1].y, local_wall[1].z);
-                        let mut t1 = Vec3::new(local_wall[0].x, top_y1, local_wall[0].z);
-                        let mut t2 = Vec3::new(local_wall[1].x, top_y2, local_wall[1].z);
-
-                        // Clip walls that are behind player at least partly
-                        if local_wall[0].z < 0.0 {
-                            clip_behind(&mut b1, &mut b2);
-                            clip_behind(&mut t1, &mut t2);
-                        }
-
-                        if local_wall[1].z < 0.0 {
-                            clip_behind(&mut b2, &mut b1);
-                            clip_behind(&mut t2, &mut t1);
-                        }
-
-                        let fov = 90.0;
-                        // Transform wall to screen coordinates
-                        let (scr_x1, scr_y1) = (
-                            b1.x * fov / b1.z + (pixel_handler.width() / 2) as f32,
-                            b1.y * fov / b1.z + (pixel_handler.height() / 2) as f32,
-                        );
-
-                        let (scr_x2, scr_y2) = (
-                            b2.x * fov / b2.z + (pixel_handler.width() / 2) as f32,
-                            b2.y * fov / b2.z + (pixel_handler.height() / 2) as f32,
-                        );
-
-                        let (_, scr_y3) = (
-                            t1.x * fov / t1.z + (pixel_handler.width() / 2) as f32,
-                            t1.y * fov / t1.z + (pixel_handler.height() / 2) as f32,
-                        );
-
-                        let (_, scr_y4) = (
-                            t2.x * fov / t2.z + (pixel_handler.width() / 2) as f32,
-                            t2.y * fov / t2.z + (pixel_handler.height() / 2) as f32,
-                        );
-
-                        // Finally draw the wall
-
-                        if let Some(image) = pixel_handler
-                            .pixel_wrapper
-                            .images()
-                            .get(&*wall_handle)
-                            .cloned()
-                        {
-                            draw_wall(
-                                IVec3::new(scr_x1 as i32, scr_y1 as i32, scr_y3 as i32),
-                                IVec3::new(scr_x2 as i32, scr_y2 as i32, scr_y4 as i32),
-                                &mut pixel_handler,
-                                (sector.surface, wall.uv),
-                                (wall.color, image.clone(), image.clone(), image),
-                                &mut sector.x_points,
-                                i,
-                            );
-                        }
-                    }
-
-                    if i == 0 {
-                        // Get the average depth
-                        sector.depth /= sector.walls.len() as f32;
-                    }
-                }
-            }
+        Self {
+            position: transform.translation,
+            cos: angle.cos(),
+            sin: angle.sin(),
+            look: angle_up.to_degrees() / 32.0,
+            center: (size / 2).as_vec2(),
         }
     }
-}
 
-pub fn bubble_sort<T: Ord>(arr: &mut [T]) {
-    for i in 0..arr.len() {
-        for j in 0..arr.len() - 1 - i {
-            if arr[j] > arr[j + 1] {
-                arr.swap(j, j + 1);
-            }
-        }
+    /// Moves a point on the map into view space, x goes to the right and y is the depth
+    fn to_view(&self, point: Vec2) -> Vec2 {
+        /*
+            In 3d graphics its more we move the world instead of the camera.
+            So first we must move all the world using the camera pos
+        */
+        let x = point.x - self.position.x;
+        let z = point.y - self.position.z;
+
+        // Use player rotation to adjust the point
+        Vec2::new(x * self.cos - z * self.sin, z * self.cos + x * self.sin)
     }
-}
 
-fn clip_behind(position_one: &mut Vec3, position_two: &mut Vec3) {
-    // Store the distance planes which are these two points
-    let da = position_one.z;
-    let db = position_two.z;
+    fn screen_x(&self, point: Vec2) -> f32 {
+        point.x * FOV / point.y + self.center.x
+    }
 
-    // Get distance of planes
-    let mut d = da - db;
-    // Prevent divide by zero
-    if db == 0.0 {
-        d = 1.0;
+    /// Screen row a world height ends up on at the given depth
+    fn screen_y(&self, height: f32, depth: f32) -> f32 {
+        (height - self.position.y + self.look * depth) * FOV / depth + self.center.y
     }
 
-    // How much the plane is intersecting ranging from 0 to 1
-    let s = da / d;
+    /// Map position seen through a pixel when looking at a floor or roof at `height`.
+    /// The depth only depends on the row, so like with visplanes each row is one distance away.
+    fn flat_point(&self, (x, y): (i32, i32), height: f32) -> Vec2 {
+        let depth = self.flat_depth(y, height);
+        let side = (x as f32 + 0.5 - self.center.x) * depth / FOV;
 
-    // Finally using intersection factor set the points to the appropiate place
-    position_one.x += s * (position_two.x - (position_one.x));
-    position_one.z += s * (position_two.z - (position_one.z));
-    if position_one.z == 0.0 {
-        position_one.z = 1.0;
+        // Undo the rotation of to_view
+        Vec2::new(
+            self.position.x + side * self.cos + depth * self.sin,
+            self.position.z + depth * self.cos - side * self.sin,
+        )
     }
-    position_one.y += s * (position_two.y - (position_one.y));
-}
 
-fn draw_wall(
-    position_one: IVec3,
-    position_two: IVec3,
-    pixel_handler: &mut PixelHandler,
-    (surface, uv): (Surface, Vec2),
-    (color, wall_image, floor_image, roof_image): (PixColor, Image, Image, Image),
-    x_points: &mut Vec<u32>,
-    front_back: usize,
-) {
-    // Horizontal step for normal surface
-    let mut ht = 0.0;
-    let ht_step =
-        wall_image.size().x as f32 * uv.x / (position_two.x as f32 - position_one.x as f32);
-
-    let mut position_one = position_one;
-    let mut position_two = position_two;
-
-    // Get distance between points
-    let dyb = position_two.y.wrapping_sub(position_one.y);
-    let dzb = position_two.z.wrapping_sub(position_one.z);
-    let mut dx = position_two.x.wrapping_sub(position_one.x);
-
-    // Prevent divide by zero
-    if dx == 0 {
-        dx = 1;
+    /// How far away a floor or roof at `height` is on a row of the screen
+    fn flat_depth(&self, y: i32, height: f32) -> f32 {
+        let horizon = self.center.y + self.look * FOV;
+        (height - self.position.y) * FOV / (y as f32 + 0.5 - horizon)
     }
+}
 
-    // Clip sides of screen
-    if position_one.x < 0 {
-        ht -= ht_step * position_one.x as f32;
-        position_one.x = 0;
-    }
+/// A sector waiting to be drawn and the columns it can be seen through
+struct RenderItem {
+    sector: SectorId,
+    columns: Range<i32>,
+    /// Portals passed through to get here
+    depth: u8,
+}
 
-    if position_two.x < 0 {
-        position_two.x = 0;
-    }
+/// Rows of every column that nothing has been drawn over yet.
+/// Solid walls close their columns and portals narrow them to the opening.
+struct ClipWindow {
+    // Inclusive
+    bottom: Vec<i32>,
+    // Exclusive
+    top: Vec<i32>,
+}
+
+/// A wall projected onto the screen
+struct ScreenWall {
+    x: [f32; 2],
+    /// View depth at both ends
+    depth: [f32; 2],
+    /// How far along the wall both ends are from 0 to 1, clipping can move them inwards
+    along: [f32; 2],
+    /// Screen rows of the floor (x) and roof (y) at both ends
+    span: [Vec2; 2],
+    /// Floor and roof rows of the sector behind a portal
+    neighbour: Option<[Vec2; 2]>,
+}
 
-    if position_one.x > (pixel_handler.width() - 1) as i32 {
-        position_one.x = (pixel_handler.width() - 1) as i32;
+impl ScreenWall {
+    /// How far across the screen wall a column is, ranging from 0 to 1
+    fn factor(&self, x: i32) -> f32 {
+        (x as f32 - self.x[0]) / (self.x[1] - self.x[0])
     }
 
-    if position_two.x > (pixel_handler.width() - 1) as i32 {
-        position_two.x = (pixel_handler.width() - 1) as i32;
+    /// How far along the wall itself a column is. Only `along / depth` and `1 / depth` change
+    /// evenly across the screen, so both are interpolated and divided back out.
+    fn along(&self, factor: f32) -> f32 {
+        let [near, far] = self.depth.map(f32::recip);
+        let inverse_depth = near + factor * (far - near);
+        let start = self.along[0] * near;
+        let end = self.along[1] * far;
+        (start + factor * (end - start)) / inverse_depth
     }
 
-    // Loop over the lines we have to draw for this wall
-    for x in position_one.x..position_two.x {
-        // Get screen y from the distances
-        // Figure out a better way to prevent overflows
-        let mut y1 = (dyb as i64 * (x as i64 - position_one.x as i64) / dx as i64
-            + position_one.y as i64) as i32;
-        let mut y2 = (dzb as i64 * (x as i64 - position_one.x as i64) / dx as i64
-            + position_one.z as i64) as i32;
-
-        // Vertical step for normal surface
-        let mut vt = 0.0;
-        let vt_step = wall_image.size().y as f32 * uv.y / (y2 as f32 - y1 as f32);
-
-        // Clip top and bottom of screen
-        if y1 < 0 {
-            vt -= vt_step * y1 as f32;
-            y1 = 0;
-        }
+    /// View depth of the wall in a column
+    fn depth(&self, factor: f32) -> f32 {
+        let [near, far] = self.depth.map(f32::recip);
+        (near + factor * (far - near)).recip()
+    }
+}
 
-        if y2 < 0 {
-            y2 = 0;
-        }
+fn draw(
+    mut pixel_handler: PixelHandler,
+    player_query: Query<(&Transform, &CurrentSector), With<Viewpoint>>,
+    (textures, cache): (Res<TextureRegistry>, Res<TextureCache>),
+    level_query: Query<&Level>,
+) {
+    let (Ok((transform, current)), Some(level)) =
+        (player_query.get_single(), level_query.iter().next())
+    else {
+        return;
+    };
+
+    let size = UVec2::new(pixel_handler.width(), pixel_handler.height());
+    let view = View::new(transform, size);
+
+    // Start from the sector we are standing in, without one there is nothing to see
+    let Some(start) = current.0.and_then(|id| level.sector(id)) else {
+        return;
+    };
+
+    let mut clip = ClipWindow {
+        bottom: vec![0; size.x as usize],
+        top: vec![size.y as i32; size.x as usize],
+    };
+    let mut queue = VecDeque::from([RenderItem {
+        sector: start.id,
+        columns: 0..size.x as i32,
+        depth: 0,
+    }]);
+
+    while let Some(item) = queue.pop_front() {
+        let Some(sector) = level.sector(item.sector) else {
+            continue;
+        };
+        let texture_of = |texture: &Option<TextureId>| cache.get(&textures, texture.as_ref()?);
+        let flats = [
+            texture_of(&sector.floor_texture),
+            texture_of(&sector.roof_texture),
+        ];
+
+        // Project every wall first, in a concave sector walls can hide each other
+        let mut visible: Vec<(&Wall, ScreenWall, Range<i32>, Option<&Sector>)> = Vec::new();
+        for wall in sector.walls.iter() {
+            let mut one = view.to_view(wall.points[0]);
+            let mut two = view.to_view(wall.points[1]);
+
+            // If the wall is behind the player we don't draw it
+            if one.y < NEAR && two.y < NEAR {
+                continue;
+            }
 
-        if y1 > (pixel_handler.height() - 1) as i32 {
-            y1 = (pixel_handler.height() - 1) as i32;
-        }
+            // Clip walls that are behind player at least partly, keeping track of where along
+            // the wall the new ends are so the texture stays in place
+            let mut along = [0.0, 1.0];
+            if one.y < NEAR {
+                let s = clip_behind(&mut one, two);
+                along[0] += s * (along[1] - along[0]);
+            }
+            if two.y < NEAR {
+                let s = clip_behind(&mut two, one);
+                along[1] += s * (along[0] - along[1]);
+            }
 
-        if y2 > (pixel_handler.height() - 1) as i32 {
-            y2 = (pixel_handler.height() - 1) as i32;
-        }
+            let x1 = view.screen_x(one);
+            let x2 = view.screen_x(two);
 
-        // Draw front wall
-        if front_back == 0 {
-            if surface == Surface::Bottom {
-                x_points[x as usize] = y1 as u32;
-            }
-            if surface == Surface::Top {
-                x_points[x as usize] = y2 as u32;
+            // Walls face into their sector, from behind we are looking at the other side
+            if x1 >= x2 {
+                continue;
             }
-            // Finally always draw the normal wall
-            for y in y1..y2 {
-                let (r, g, b) = image_rgb_u8(&wall_image, UVec2::new(ht as u32, vt as u32));
-                vt += vt_step;
 
-                pixel_handler.set_pixel(UVec2::new(x as u32, y as u32), PixColor(r, g, b, 255));
+            let columns = (x1.ceil().max(item.columns.start as f32) as i32)
+                ..(x2.ceil().min(item.columns.end as f32) as i32);
+            if columns.is_empty() {
+                continue;
             }
-            ht += ht_step;
+
+            let span = |floor: f32, roof: f32| {
+                [
+                    Vec2::new(view.screen_y(floor, one.y), view.screen_y(roof, one.y)),
+                    Vec2::new(view.screen_y(floor, two.y), view.screen_y(roof, two.y)),
+                ]
+            };
+            let neighbour = wall.portal.and_then(|id| level.sector(id));
+            let screen_wall = ScreenWall {
+                x: [x1, x2],
+                depth: [one.y, two.y],
+                along,
+                span: span(sector.floor, sector.roof),
+                neighbour: neighbour.map(|neighbour| span(neighbour.floor, neighbour.roof)),
+            };
+
+            visible.push((wall, screen_wall, columns, neighbour));
         }
 
-        // Draw back wall and surface
-        if front_back == 1 {
-            let mut color = color;
-            if surface == Surface::Bottom {
-                y2 = x_points[x as usize] as i32;
-                // color = roof_col;
-            }
-            if surface == Surface::Top {
-                y1 = x_points[x as usize] as i32;
-                // color = floor_col;
+        // Only the nearest wall of the sector can be seen in a column, whatever order they are in
+        let mut nearest: Vec<Option<(usize, f32)>> = vec![None; item.columns.len()];
+        for (index, (_, screen_wall, columns, _)) in visible.iter().enumerate() {
+            for x in columns.clone() {
+                let depth = screen_wall.depth(screen_wall.factor(x));
+                let slot = &mut nearest[(x - item.columns.start) as usize];
+                if slot.is_none_or(|(_, nearest)| depth < nearest) {
+                    *slot = Some((index, depth));
+                }
             }
-            for y in y1..y2 {
-                pixel_handler.set_pixel(UVec2::new(x as u32, y as u32), color);
+        }
+
+        for (index, (wall, screen_wall, columns, neighbour)) in visible.iter().enumerate() {
+            let owns = |x: i32| {
+                nearest[(x - item.columns.start) as usize].is_some_and(|(owner, _)| owner == index)
+            };
+            let wall_textures = [
+                texture_of(&wall.texture),
+                texture_of(&wall.upper.texture),
+                texture_of(&wall.lower.texture),
+            ];
+
+            // Draw each stretch of columns the wall is in front in
+            let mut x = columns.start;
+            while x < columns.end {
+                if !owns(x) {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < columns.end && owns(x) {
+                    x += 1;
+                }
+                draw_wall(
+                    screen_wall,
+                    start..x,
+                    &mut pixel_handler,
+                    &mut clip,
+                    (wall, sector, *neighbour, &view),
+                    (wall_textures, flats),
+                );
+
+                // Continue into the next sector through whatever is left of the opening
+                match neighbour {
+                    Some(neighbour) if item.depth < MAX_PORTAL_DEPTH => {
+                        queue.push_back(RenderItem {
+                            sector: neighbour.id,
+                            columns: start..x,
+                            depth: item.depth + 1,
+                        });
+                    }
+                    _ => {}
+                }
             }
         }
     }
 }
 
-fn image_rgb_u8(image: &Image, step: UVec2) -> (u8, u8, u8) {
-    let pixel = ((image.size().y as u32 - step.y.rem_euclid(image.size().y as u32) - 1)
-        * 8
-        * image.size().x as u32
-        + (step.x.rem_euclid(image.size().x as u32) * 8)) as usize;
-    let r = ((image.data[pixel + 0] as u16) << 8) | image.data[pixel + 1] as u16;
-    let g = ((image.data[pixel + 2] as u16) << 8) | image.data[pixel + 3] as u16;
-    let b = ((image.data[pixel + 4] as u16) << 8) | image.data[pixel + 5] as u16;
-    (r as u8, g as u8, b as u8)
+/// Moves `position_one` up to the near plane, returns how far towards `position_two` it went
+fn clip_behind(position_one: &mut Vec2, position_two: Vec2) -> f32 {
+    // How far along the wall the near plane is crossed ranging from 0 to 1
+    let s = (NEAR - position_one.y) / (position_two.y - position_one.y);
+
+    // Finally using intersection factor set the point to the appropiate place
+    *position_one += s * (position_two - *position_one);
+    s
+}
+
+fn draw_wall(
+    screen_wall: &ScreenWall,
+    columns: Range<i32>,
+    pixel_handler: &mut PixelHandler,
+    clip: &mut ClipWindow,
+    (wall, sector, neighbour, view): (&Wall, &Sector, Option<&Sector>, &View),
+    ([wall_texture, upper_texture, lower_texture], [floor_texture, roof_texture]): (
+        [Option<&Texture>; 3],
+        [Option<&Texture>; 2],
+    ),
+) {
+    let length = wall.length();
+    for x in columns {
+        let column = x as usize;
+        let (bottom, top) = (clip.bottom[column], clip.top[column]);
+        if bottom >= top {
+            continue;
+        }
+
+        // Get screen y of the floor and roof in this column
+        let factor = screen_wall.factor(x);
+        let Vec2 {
+            x: floor_y,
+            y: roof_y,
+        } = screen_wall.span[0].lerp(screen_wall.span[1], factor);
+        let along = screen_wall.along(factor) * length;
+        let lit = brightness(sector.light + wall.light, screen_wall.depth(factor));
+        let y1 = (floor_y as i32).clamp(bottom, top);
+        let y2 = (roof_y as i32).clamp(bottom, top);
+
+        // Roof above the wall and floor below it
+        draw_flat_span(
+            pixel_handler,
+            (x, y2..top),
+            (view, sector.roof, sector.light),
+            sector.roof_col,
+            roof_texture,
+        );
+        draw_flat_span(
+            pixel_handler,
+            (x, bottom..y1),
+            (view, sector.floor, sector.light),
+            sector.floor_col,
+            floor_texture,
+        );
+
+        if let Some((span, neighbour)) = screen_wall.neighbour.zip(neighbour) {
+            // Only the part of the opening both sectors share can be looked through
+            let neighbour_span = span[0].lerp(span[1], factor);
+            let ny1 = (neighbour_span.x as i32).clamp(y1, y2);
+            let ny2 = (neighbour_span.y as i32).clamp(ny1, y2);
+
+            // Step walls where the neighbours floor is higher or its roof lower
+            draw_wall_span(
+                pixel_handler,
+                (x, along),
+                (
+                    y1..ny1,
+                    (floor_y, neighbour_span.x),
+                    (sector.floor, neighbour.floor),
+                ),
+                (wall.lower.color, &wall.lower.mapping, lit),
+                lower_texture,
+            );
+            draw_wall_span(
+                pixel_handler,
+                (x, along),
+                (
+                    ny2..y2,
+                    (neighbour_span.y, roof_y),
+                    (neighbour.roof, sector.roof),
+                ),
+                (wall.upper.color, &wall.upper.mapping, lit),
+                upper_texture,
+            );
+
+            clip.bottom[column] = ny1;
+            clip.top[column] = ny2;
+            continue;
+        }
+
+        draw_wall_span(
+            pixel_handler,
+            (x, along),
+            (y1..y2, (floor_y, roof_y), (sector.floor, sector.roof)),
+            (wall.color, &wall.mapping, lit),
+            wall_texture,
+        );
+
+        // Nothing behind a solid wall can be seen
+        clip.top[column] = clip.bottom[column];
+    }
+}
+
+/// Textures the visible rows of one column of a wall reaching from the world height `bottom` to
+/// `top`, which end up on the screen rows `bottom_y` and `top_y`. `along` is in world units.
+/// The whole column is equally far away, so it is shaded with a single `brightness`.
+fn draw_wall_span(
+    pixel_handler: &mut PixelHandler,
+    (x, along): (i32, f32),
+    (rows, (bottom_y, top_y), heights): (Range<i32>, (f32, f32), (f32, f32)),
+    (color, mapping, brightness): (PixColor, &TextureMapping, f32),
+    texture: Option<&Texture>,
+) {
+    let Some(texture) = texture else {
+        draw_span(pixel_handler, x, rows, shade(color, brightness));
+        return;
+    };
+
+    // Heights change evenly down a column, so the texture does too
+    let bottom = mapping.uv(along, heights.0, heights) * texture.size();
+    let top = mapping.uv(along, heights.1, heights) * texture.size();
+    let vt_step = (top.y - bottom.y) / (top_y - bottom_y);
+    let column = texture.column(bottom.x.floor() as i32);
+    for y in rows {
+        let vt = bottom.y + (y as f32 - bottom_y) * vt_step;
+        let color = shade(column.sample(vt.floor() as i32), brightness);
+        pixel_handler.set_pixel(UVec2::new(x as u32, y as u32), color);
+    }
+}
+
+/// Textures and shades the visible rows of one column of a floor or roof at `height`,
+/// each row is a different distance away so it gets darker towards the horizon
+fn draw_flat_span(
+    pixel_handler: &mut PixelHandler,
+    (x, rows): (i32, Range<i32>),
+    (view, height, light): (&View, f32, f32),
+    color: PixColor,
+    texture: Option<&Texture>,
+) {
+    for y in rows {
+        let color = match texture {
+            Some(texture) => {
+                let texels_per_unit = texture.size() / TEXTURE_SIZE;
+                let texel = (view.flat_point((x, y), height) * texels_per_unit).floor();
+                texture.sample(texel.x as i32, texel.y as i32)
+            }
+            None => color,
+        };
+        let lit = brightness(light, view.flat_depth(y, height));
+        pixel_handler.set_pixel(UVec2::new(x as u32, y as u32), shade(color, lit));
+    }
+}
+
+fn draw_span(pixel_handler: &mut PixelHandler, x: i32, rows: Range<i32>, color: PixColor) {
+    for y in rows {
+        pixel_handler.set_pixel(UVec2::new(x as u32, y as u32), color);
+    }
 }
 
 // fn test_textures(image: &Image, pixel_handler: &mut PixelHandler) {
@@ -0,0 +1,83 @@
+use bevy::{prelude::*, render::render_resource::TextureFormat};
+
+/// Shown for texels of formats that can't be read, bright so missing support stands out
+const UNSUPPORTED: [u8; 4] = [255, 0, 255, 255];
+
+/// Reads the texel at `x` and `y`, counted from the top left, as 8-bit sRGB with alpha.
+///
+/// PNGs load as 8-bit, 16-bit, grey or grey with alpha depending on how they were saved,
+/// their values get used as they are. Float formats hold linear light and are turned into sRGB.
+pub fn read_texel(image: &Image, x: u32, y: u32) -> [u8; 4] {
+    let width = image.texture_descriptor.size.width;
+    let index = (y * width + x) as usize;
+    let data = &image.data;
+    let bytes = |size: usize| data.get(index * size..(index + 1) * size);
+
+    let texel = match image.texture_descriptor.format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {
+            bytes(4).map(|texel| [texel[0], texel[1], texel[2], texel[3]])
+        }
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+            bytes(4).map(|texel| [texel[2], texel[1], texel[0], texel[3]])
+        }
+        TextureFormat::R8Unorm => bytes(1).map(|texel| [texel[0], texel[0], texel[0], 255]),
+        TextureFormat::Rg8Unorm => bytes(2).map(|texel| [texel[0], texel[0], texel[0], texel[1]]),
+        TextureFormat::Rgba16Uint | TextureFormat::Rgba16Unorm => bytes(8).map(|texel| {
+            let channel = |i: usize| high_byte([texel[i * 2], texel[i * 2 + 1]]);
+            [channel(0), channel(1), channel(2), channel(3)]
+        }),
+        TextureFormat::R16Uint | TextureFormat::R16Unorm => bytes(2).map(|texel| {
+            let grey = high_byte([texel[0], texel[1]]);
+            [grey, grey, grey, 255]
+        }),
+        TextureFormat::Rg16Uint | TextureFormat::Rg16Unorm => bytes(4).map(|texel| {
+            let grey = high_byte([texel[0], texel[1]]);
+            [grey, grey, grey, high_byte([texel[2], texel[3]])]
+        }),
+        TextureFormat::Rgba16Float => bytes(8).map(|texel| {
+            let channel =
+                |i: usize| f16_to_f32(u16::from_ne_bytes([texel[i * 2], texel[i * 2 + 1]]));
+            float_texel([channel(0), channel(1), channel(2), channel(3)])
+        }),
+        TextureFormat::Rgba32Float => bytes(16).map(|texel| {
+            let channel = |i: usize| {
+                f32::from_ne_bytes([
+                    texel[i * 4],
+                    texel[i * 4 + 1],
+                    texel[i * 4 + 2],
+                    texel[i * 4 + 3],
+                ])
+            };
+            float_texel([channel(0), channel(1), channel(2), channel(3)])
+        }),
+        TextureFormat::R32Float => bytes(4).map(|texel| {
+            let grey = f32::from_ne_bytes([texel[0], texel[1], texel[2], texel[3]]);
+            float_texel([grey, grey, grey, 1.0])
+        }),
+        _ => None,
+    };
+    texel.unwrap_or(UNSUPPORTED)
+}
+
+/// Top 8 bits of a 16-bit channel
+fn high_byte(bytes: [u8; 2]) -> u8 {
+    (u16::from_ne_bytes(bytes) >> 8) as u8
+}
+
+fn float_texel([r, g, b, a]: [f32; 4]) -> [u8; 4] {
+    let srgb = Color::rgba_linear(r, g, b, a).as_rgba_f32();
+    srgb.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Widens a half float, which nothing in std does yet
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,116 @@
+use bevy::{asset::HandleId, prelude::*, utils::HashMap};
+use portal_common::prelude::*;
+
+use crate::texel::read_texel;
+
+/// An image converted once into the layout drawing reads fastest
+pub struct Texture {
+    width: u32,
+    height: u32,
+    /// `width - 1` and `height - 1` when both are powers of two, so wrapping is one bitwise AND
+    mask: Option<UVec2>,
+    /// Packed RGBA column after column, each running up from the bottom row of the image,
+    /// so drawing a wall column walks through memory in order
+    texels: Vec<u32>,
+}
+
+impl Texture {
+    pub fn from_image(image: &Image) -> Self {
+        let size = image.texture_descriptor.size;
+        let (width, height) = (size.width, size.height);
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for x in 0..width {
+            for v in 0..height {
+                // Images store their rows from the top, and nothing gets drawn see-through
+                let [r, g, b, _] = read_texel(image, x, height - 1 - v);
+                texels.push(u32::from_le_bytes([r, g, b, 255]));
+            }
+        }
+        let mask = (width.is_power_of_two() && height.is_power_of_two())
+            .then(|| UVec2::new(width - 1, height - 1));
+        Self {
+            width,
+            height,
+            mask,
+            texels,
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32)
+    }
+
+    /// Texels of column `u` from the bottom up, repeating sideways
+    pub fn column(&self, u: i32) -> Column<'_> {
+        let x = match self.mask {
+            Some(mask) => u as u32 & mask.x,
+            None => u.rem_euclid(self.width as i32) as u32,
+        };
+        let start = (x * self.height) as usize;
+        Column {
+            texels: &self.texels[start..start + self.height as usize],
+            mask: self.mask.map(|mask| mask.y),
+        }
+    }
+
+    /// Texel at `u` and `v` counted from the bottom left, repeating in both directions
+    pub fn sample(&self, u: i32, v: i32) -> PixColor {
+        self.column(u).sample(v)
+    }
+}
+
+/// One column of a [`Texture`]
+pub struct Column<'a> {
+    texels: &'a [u32],
+    mask: Option<u32>,
+}
+
+impl Column<'_> {
+    /// Texel `v` rows up from the bottom, repeating
+    pub fn sample(&self, v: i32) -> PixColor {
+        let y = match self.mask {
+            Some(mask) => v as u32 & mask,
+            None => v.rem_euclid(self.texels.len() as i32) as u32,
+        };
+        let [r, g, b, a] = self.texels[y as usize].to_le_bytes();
+        PixColor(r, g, b, a)
+    }
+}
+
+/// Converted copies of every texture in the [`TextureRegistry`] that has finished loading
+#[derive(Resource, Default)]
+pub struct TextureCache(HashMap<HandleId, Texture>);
+
+impl TextureCache {
+    /// The converted texture for an id, `None` while it is unknown or still loading
+    pub fn get(&self, registry: &TextureRegistry, id: &TextureId) -> Option<&Texture> {
+        self.0.get(&registry.get(id)?.id())
+    }
+}
+
+/// Converts textures once they load and again whenever they are reloaded
+pub fn update_texture_cache(
+    mut events: EventReader<AssetEvent<Image>>,
+    images: Res<Assets<Image>>,
+    registry: Res<TextureRegistry>,
+    mut cache: ResMut<TextureCache>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } | AssetEvent::Removed { handle } = event {
+            cache.0.remove(&handle.id());
+        }
+    }
+    // Only textures, the pixel buffer is an image too but changes every frame
+    for (_, handle) in registry.iter() {
+        if cache.0.contains_key(&handle.id()) {
+            continue;
+        }
+        // Empty images have nothing to repeat
+        if let Some(image) = images
+            .get(handle)
+            .filter(|image| image.size().min_element() > 0.0)
+        {
+            cache.0.insert(handle.id(), Texture::from_image(image));
+        }
+    }
+}
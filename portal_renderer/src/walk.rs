@@ -0,0 +1,59 @@
+use bevy::prelude::*;
+use portal_common::prelude::*;
+
+use crate::collision::Collider;
+
+/// Downwards acceleration in map units per second squared
+const GRAVITY: f32 = 240.0;
+/// Upwards speed given by a jump, enough to get onto a ledge a bit higher than a step
+const JUMP_SPEED: f32 = 80.0;
+
+/// Keeps the viewpoint on the floor while walking, flying ignores floors and walls alike
+#[derive(Component, Default, Debug)]
+pub struct Walk {
+    /// Noclip, moving up and down freely and passing through walls
+    pub flying: bool,
+    /// Speed upwards while in the air
+    pub vertical_speed: f32,
+    pub on_ground: bool,
+}
+
+impl Walk {
+    /// Moves the view height `eye` for one frame of walking in `sector`
+    pub fn update(
+        &mut self,
+        eye: &mut f32,
+        sector: &Sector,
+        collider: &Collider,
+        dt: f32,
+        jump: bool,
+    ) {
+        let mut feet = *eye - collider.eye_height;
+
+        if self.on_ground && jump {
+            self.vertical_speed = JUMP_SPEED;
+        }
+        if feet <= sector.floor && self.vertical_speed <= 0.0 {
+            // Standing, or just walked up a step
+            feet = sector.floor;
+            self.vertical_speed = 0.0;
+            self.on_ground = true;
+        } else {
+            self.vertical_speed -= GRAVITY * dt;
+            feet += self.vertical_speed * dt;
+            self.on_ground = false;
+            if feet <= sector.floor {
+                feet = sector.floor;
+                self.vertical_speed = 0.0;
+                self.on_ground = true;
+            }
+        }
+
+        // Bump the head on the roof
+        if feet + collider.eye_height > sector.roof {
+            feet = (sector.roof - collider.eye_height).max(sector.floor);
+            self.vertical_speed = self.vertical_speed.min(0.0);
+        }
+        *eye = feet + collider.eye_height;
+    }
+}